@@ -0,0 +1,4 @@
+ALTER TABLE routes
+    DROP COLUMN visits,
+    DROP COLUMN unique_visits;
+DROP TABLE clicks;
@@ -0,0 +1,19 @@
+CREATE TABLE clicks (
+    id BIGSERIAL PRIMARY KEY,
+    route_id UUID NOT NULL,
+    clicked_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    referrer VARCHAR,
+    user_agent VARCHAR,
+    ip_hash VARCHAR, -- salted hash, raw client IPs are never stored
+    CONSTRAINT fk_route
+        FOREIGN KEY(route_id)
+        REFERENCES routes(id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX clicks_route_id_clicked_at ON clicks(route_id, clicked_at);
+CREATE INDEX clicks_route_id_ip_hash ON clicks(route_id, ip_hash);
+
+ALTER TABLE routes
+    ADD COLUMN visits BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN unique_visits BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+ALTER TABLE clicks
+    DROP COLUMN browser,
+    DROP COLUMN os,
+    DROP COLUMN country;
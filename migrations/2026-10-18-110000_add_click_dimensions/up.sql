@@ -0,0 +1,4 @@
+ALTER TABLE clicks
+    ADD COLUMN browser VARCHAR, -- parsed from user_agent when recorded
+    ADD COLUMN os VARCHAR,
+    ADD COLUMN country VARCHAR(2); -- ISO 3166-1 alpha-2
@@ -0,0 +1,7 @@
+-- secret sealed with two_factor.key, set on enroll and kept once confirmed
+ALTER TABLE users ADD COLUMN totp_secret BYTEA;
+ALTER TABLE users ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT 'f';
+-- last accepted time step, a code is never accepted twice
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT NOT NULL DEFAULT 0;
+-- sha256 of unused recovery codes
+ALTER TABLE users ADD COLUMN recovery_codes TEXT[] NOT NULL DEFAULT '{}';
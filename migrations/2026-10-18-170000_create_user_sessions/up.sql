@@ -0,0 +1,17 @@
+-- one row per logged in cookie session, deleting it logs that session out
+CREATE TABLE user_sessions (
+    id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    user_id UUID NOT NULL,
+    user_agent VARCHAR,
+    browser VARCHAR,
+    os VARCHAR,
+    ip VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    last_seen_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT fk_user
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX user_sessions_user_id ON user_sessions(user_id);
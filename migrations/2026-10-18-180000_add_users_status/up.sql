@@ -0,0 +1,4 @@
+-- set together with active = false when an admin suspends the account,
+-- accounts deactivated by their owner leave them empty
+ALTER TABLE users ADD COLUMN suspended_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN suspension_reason TEXT;
@@ -0,0 +1,7 @@
+DROP TRIGGER audit_events_no_truncate ON audit_events;
+DROP TRIGGER audit_events_append_only ON audit_events;
+DROP FUNCTION audit_events_append_only();
+ALTER TABLE audit_events DROP COLUMN ip;
+ALTER TABLE audit_events DROP COLUMN after;
+ALTER TABLE audit_events DROP COLUMN before;
+ALTER TABLE audit_events DROP COLUMN user_id;
@@ -0,0 +1,40 @@
+-- the account whose history an event belongs to, the actor for their own
+-- actions and the affected user (or route owner) for admin actions
+ALTER TABLE audit_events ADD COLUMN user_id UUID REFERENCES users (id) ON DELETE SET NULL;
+ALTER TABLE audit_events ADD COLUMN before JSONB;
+ALTER TABLE audit_events ADD COLUMN after JSONB;
+ALTER TABLE audit_events ADD COLUMN ip VARCHAR;
+
+UPDATE audit_events SET user_id = target_id
+    WHERE target_type = 'user' AND target_id IN (SELECT id FROM users);
+UPDATE audit_events SET user_id = routes.creator_id
+    FROM routes WHERE target_type = 'route' AND routes.id = audit_events.target_id;
+
+CREATE INDEX audit_events_user_id_idx ON audit_events (user_id, created_at);
+
+-- rows are never changed, except for ON DELETE SET NULL when a user is deleted
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+    -- NEW is unset for DELETE and TRUNCATE, so only look at it for UPDATE
+    IF TG_OP = 'UPDATE' THEN
+        IF (NEW.actor_id IS NULL OR NEW.actor_id = OLD.actor_id)
+            AND (NEW.user_id IS NULL OR NEW.user_id = OLD.user_id)
+            AND (NEW.id, NEW.action, NEW.target_type, NEW.target_id, NEW.details,
+                 NEW.before, NEW.after, NEW.ip, NEW.created_at)
+                IS NOT DISTINCT FROM
+                (OLD.id, OLD.action, OLD.target_type, OLD.target_id, OLD.details,
+                 OLD.before, OLD.after, OLD.ip, OLD.created_at)
+        THEN
+            RETURN NEW;
+        END IF;
+    END IF;
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE PROCEDURE audit_events_append_only();
+CREATE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE PROCEDURE audit_events_append_only();
@@ -0,0 +1,21 @@
+CREATE TABLE route_versions (
+    id BIGSERIAL PRIMARY KEY,
+    route_id UUID NOT NULL REFERENCES routes (id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    slug VARCHAR NOT NULL,
+    target VARCHAR NOT NULL,
+    active BOOLEAN NOT NULL,
+    active_from TIMESTAMP,
+    active_till TIMESTAMP,
+    changed_by UUID REFERENCES users (id) ON DELETE SET NULL,
+    -- set when this version restored an older one
+    rolled_back_from INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (route_id, version)
+);
+
+-- what routes look like now is their first version
+INSERT INTO route_versions
+    (route_id, version, slug, target, active, active_from, active_till, changed_by, created_at)
+SELECT id, 1, slug, target, active, active_from, active_till, creator_id, updated_at
+FROM routes;
@@ -0,0 +1,14 @@
+-- target changes that take effect at a set time, applied to routes.target by a
+-- background job and honored by redirects as soon as they are due
+CREATE TABLE route_schedules (
+    id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    route_id UUID NOT NULL REFERENCES routes (id) ON DELETE CASCADE,
+    effective_at TIMESTAMP NOT NULL,
+    target VARCHAR NOT NULL,
+    created_by UUID REFERENCES users (id) ON DELETE SET NULL,
+    applied_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX route_schedules_route_id ON route_schedules (route_id, effective_at);
+CREATE INDEX route_schedules_due ON route_schedules (effective_at) WHERE applied_at IS NULL;
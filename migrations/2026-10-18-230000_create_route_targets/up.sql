@@ -0,0 +1,16 @@
+-- weighted targets of a route for A/B splits, a route without any uses routes.target
+CREATE TABLE route_targets (
+    id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    route_id UUID NOT NULL REFERENCES routes (id) ON DELETE CASCADE,
+    target VARCHAR NOT NULL,
+    -- 0 pauses the variant without losing its clicks
+    weight INTEGER NOT NULL CHECK (weight >= 0),
+    label VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX route_targets_route_id ON route_targets (route_id);
+
+-- the variant a click was sent to
+ALTER TABLE clicks ADD COLUMN variant_id UUID REFERENCES route_targets (id) ON DELETE SET NULL;
+CREATE INDEX clicks_variant_id ON clicks (variant_id) WHERE variant_id IS NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE route_versions DROP COLUMN rules;
+ALTER TABLE routes DROP COLUMN rules;
@@ -0,0 +1,3 @@
+-- ordered targeting rules, the first matching one overrides splits and the route's target
+ALTER TABLE routes ADD COLUMN rules JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE route_versions ADD COLUMN rules JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,4 @@
+DROP INDEX route_schedules_due;
+CREATE INDEX route_schedules_due ON route_schedules (effective_at) WHERE applied_at IS NULL;
+
+ALTER TABLE route_schedules DROP COLUMN superseded_at;
@@ -0,0 +1,7 @@
+-- set when the owner changed the target by hand after a schedule was due but before
+-- the background job applied it, such schedules are never applied
+ALTER TABLE route_schedules ADD COLUMN superseded_at TIMESTAMP;
+
+DROP INDEX route_schedules_due;
+CREATE INDEX route_schedules_due ON route_schedules (effective_at)
+    WHERE applied_at IS NULL AND superseded_at IS NULL;
@@ -0,0 +1 @@
+ALTER TABLE route_versions DROP COLUMN variants;
@@ -0,0 +1,3 @@
+-- variants of the route as they were after the change, NULL for versions recorded
+-- before variants were kept, rolling back to those leaves the variants alone
+ALTER TABLE route_versions ADD COLUMN variants JSONB;
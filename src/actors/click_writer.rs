@@ -0,0 +1,191 @@
+use crate::actix::prelude::*;
+use crate::actix::utils::Condition;
+use crate::actors::db::clicks::{RecordClicks, UnrecordedClicks};
+use crate::actors::db::DbActor;
+use crate::models::clicks::NewClick;
+use std::time::Duration;
+
+/// Clicks are written at least this often
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// A flush is triggered early once this many clicks are buffered
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+/// Clicks beyond this are dropped (and logged) instead of slowing redirects
+pub const DEFAULT_BUFFER_CAPACITY: usize = 10_000;
+/// Upper bound for the buffer capacity, keeps a stalled database from eating memory
+pub const MAX_BUFFER_CAPACITY: usize = 1_000_000;
+
+/// Buffers clicks in memory and writes them to the database in bulk, so that
+/// redirects only pay for a mailbox push
+pub struct ClickWriter {
+    db: Addr<DbActor>,
+    buffer: Vec<NewClick>,
+    flush_interval: Duration,
+    batch_size: usize,
+    capacity: usize,
+    /// a batch is being written, the next one waits for it
+    flushing: bool,
+    /// told when the batch being written is done, and back in the buffer if it failed
+    written: Condition<()>,
+    /// Flush is writing out everything, no new batches are started meanwhile
+    draining: bool,
+    /// clicks dropped because the buffer was full
+    dropped: u64,
+    /// value of `dropped` when it was last logged
+    reported_dropped: u64,
+}
+
+impl ClickWriter {
+    pub fn new(
+        db: Addr<DbActor>,
+        flush_interval: Duration,
+        batch_size: usize,
+        capacity: usize,
+    ) -> Self {
+        ClickWriter {
+            db,
+            buffer: Vec::with_capacity(batch_size),
+            flush_interval,
+            batch_size,
+            capacity,
+            flushing: false,
+            written: Condition::default(),
+            draining: false,
+            dropped: 0,
+            reported_dropped: 0,
+        }
+    }
+
+    /// Hands the buffered clicks to the db actor, a failed batch is buffered again
+    fn flush(&mut self, ctx: &mut Context<Self>) {
+        if self.dropped != self.reported_dropped {
+            error!(
+                "click buffer full, dropped {} clicks ({} since start)",
+                self.dropped - self.reported_dropped,
+                self.dropped
+            );
+            self.reported_dropped = self.dropped;
+        }
+        if self.flushing || self.draining || self.buffer.is_empty() {
+            return;
+        }
+
+        self.flushing = true;
+        let batch = std::mem::take(&mut self.buffer);
+        let count = batch.len();
+        self.db
+            .send(RecordClicks { clicks: batch })
+            .into_actor(self)
+            .map(move |res, act, _| {
+                act.flushing = false;
+                match res {
+                    Ok(Ok(())) => {}
+                    Ok(Err(UnrecordedClicks { error, clicks })) => {
+                        error!("failed to write {} clicks: {}", count, error);
+                        act.rebuffer(clicks);
+                    }
+                    // the db actor is gone and the batch with it
+                    Err(err) => error!("lost {} clicks: {}", count, err),
+                }
+                std::mem::take(&mut act.written).set(());
+            })
+            .spawn(ctx);
+    }
+
+    /// Puts a failed batch back in front of the clicks that arrived meanwhile, as
+    /// far as the capacity allows
+    fn rebuffer(&mut self, mut batch: Vec<NewClick>) {
+        let room = self.capacity.saturating_sub(self.buffer.len());
+        if batch.len() > room {
+            self.dropped += (batch.len() - room) as u64;
+            batch.truncate(room);
+        }
+        batch.append(&mut self.buffer);
+        self.buffer = batch;
+    }
+}
+
+impl Actor for ClickWriter {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.flush_interval, |act, ctx| act.flush(ctx));
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        // futures spawned on a stopping context never run, so block on the last write
+        if !self.buffer.is_empty() {
+            let batch = std::mem::take(&mut self.buffer);
+            let count = batch.len();
+            match futures::executor::block_on(self.db.send(RecordClicks { clicks: batch })) {
+                Ok(Ok(())) => info!("flushed {} buffered clicks", count),
+                _ => error!("failed to flush {} buffered clicks", count),
+            }
+        }
+        Running::Stop
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct TrackClick {
+    pub click: NewClick,
+}
+
+impl Handler<TrackClick> for ClickWriter {
+    type Result = ();
+
+    fn handle(&mut self, msg: TrackClick, ctx: &mut Self::Context) -> Self::Result {
+        if self.buffer.len() >= self.capacity {
+            self.dropped += 1;
+            return;
+        }
+        self.buffer.push(msg.click);
+        if self.buffer.len() >= self.batch_size {
+            self.flush(ctx);
+        }
+    }
+}
+
+/// Writes out everything buffered and waits for the insert, used on shutdown.
+/// A batch still being written is waited for first, so it is written again if it
+/// failed
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Flush;
+
+impl Handler<Flush> for ClickWriter {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, _: Flush, _: &mut Self::Context) -> Self::Result {
+        self.draining = true;
+        let in_flight = if self.flushing {
+            Some(self.written.wait())
+        } else {
+            None
+        };
+
+        let write = async move {
+            if let Some(written) = in_flight {
+                let _ = written.await;
+            }
+        }
+        .into_actor(self)
+        .then(|_, act, _| {
+            let batch = std::mem::take(&mut act.buffer);
+            let count = batch.len();
+            let db = act.db.clone();
+            async move {
+                if count == 0 {
+                    return;
+                }
+                match db.send(RecordClicks { clicks: batch }).await {
+                    Ok(Ok(())) => info!("flushed {} buffered clicks", count),
+                    _ => error!("failed to flush {} buffered clicks", count),
+                }
+            }
+            .into_actor(act)
+        })
+        .map(|_, act, _| act.draining = false);
+        Box::pin(write)
+    }
+}
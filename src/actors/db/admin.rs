@@ -0,0 +1,220 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::audit::record_in;
+use crate::actors::db::route_versions::record_version;
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::admin::SystemStats;
+use crate::models::audit::NewAuditEvent;
+use crate::models::routes::{AdminRouteInfo, Route};
+use crate::models::users::{User, ROLE_ADMIN};
+use crate::schema::{clicks, routes, user_sessions, users};
+use chrono::{Duration, Utc};
+use diesel::dsl::sql;
+use diesel::sql_types::BigInt;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Users whose name, username or email contains `query`, newest first
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<User>>")]
+pub struct SearchUsers {
+    pub query: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Routes whose slug or target contains `query`, newest first
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<Route>>")]
+pub struct SearchRoutes {
+    pub query: Option<String>,
+    pub creator_id: Option<Uuid>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Hands a route to another user, NotFound when either does not exist
+#[derive(Message)]
+#[rtype(result = "QueryResult<Route>")]
+pub struct ReassignRoute {
+    pub id: Uuid,
+    pub creator_id: Uuid,
+    /// the admin, recorded in the route's history
+    pub changed_by: Uuid,
+    /// written in the same transaction with the route as it was before and is after,
+    /// as admins see it
+    pub audit: NewAuditEvent,
+}
+
+/// Sets the active flag of any route, whoever owns it
+#[derive(Message)]
+#[rtype(result = "QueryResult<Route>")]
+pub struct SetRouteActive {
+    pub id: Uuid,
+    pub active: bool,
+    /// the admin, recorded in the route's history
+    pub changed_by: Uuid,
+    /// written in the same transaction, see `ReassignRoute::audit`. Actor and owner
+    /// are filled in from `changed_by` and the route
+    pub audit: NewAuditEvent,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<SystemStats>")]
+pub struct GetSystemStats;
+
+/// ILIKE pattern matching `query` anywhere, with its wildcards taken literally
+fn contains_pattern(query: &str) -> String {
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl Handler<SearchUsers> for DbActor {
+    type Result = QueryResult<Vec<User>>;
+
+    fn handle(&mut self, msg: SearchUsers, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        let mut query = users::table.into_boxed();
+        if let Some(text) = msg.query {
+            let pattern = contains_pattern(&text);
+            query = query.filter(
+                users::username
+                    .ilike(pattern.clone())
+                    .or(users::name.ilike(pattern.clone()))
+                    .or(users::email.ilike(pattern)),
+            );
+        }
+        query
+            .order((users::created_at.desc(), users::id))
+            .limit(msg.limit)
+            .offset(msg.offset)
+            .load(&conn)
+    }
+}
+
+impl Handler<SearchRoutes> for DbActor {
+    type Result = QueryResult<Vec<Route>>;
+
+    fn handle(&mut self, msg: SearchRoutes, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        let mut query = routes::table.into_boxed();
+        if let Some(text) = msg.query {
+            let pattern = contains_pattern(&text);
+            query = query.filter(
+                routes::slug
+                    .ilike(pattern.clone())
+                    .or(routes::target.ilike(pattern)),
+            );
+        }
+        if let Some(owner) = msg.creator_id {
+            query = query.filter(routes::creator_id.eq(owner));
+        }
+        query
+            .order((routes::created_at.desc(), routes::id))
+            .limit(msg.limit)
+            .offset(msg.offset)
+            .load(&conn)
+    }
+}
+
+/// The route, locked until the transaction ends so its version and audit row see it
+/// as it was right before the change
+fn lock_route(conn: &PgConnection, route_id: Uuid) -> QueryResult<Route> {
+    routes::table.find(route_id).for_update().get_result(conn)
+}
+
+impl Handler<ReassignRoute> for DbActor {
+    type Result = QueryResult<Route>;
+
+    fn handle(&mut self, msg: ReassignRoute, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            users::table
+                .find(msg.creator_id)
+                .get_result::<User>(&conn)?;
+            let before = lock_route(&conn, msg.id)?;
+            let route = diesel::update(routes::table.find(msg.id))
+                .set(routes::creator_id.eq(msg.creator_id))
+                .get_result::<Route>(&conn)?;
+            record_version(&conn, &route, Some(msg.changed_by), None)?;
+            let audit = msg
+                .audit
+                .details(json!({ "previous_owner": before.creator_id }))
+                .before(&AdminRouteInfo::from(before))
+                .after(&AdminRouteInfo::from(route.clone()));
+            record_in(&conn, audit)?;
+            Ok(route)
+        })
+    }
+}
+
+impl Handler<SetRouteActive> for DbActor {
+    type Result = QueryResult<Route>;
+
+    fn handle(&mut self, msg: SetRouteActive, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let before = lock_route(&conn, msg.id)?;
+            let route = diesel::update(routes::table.find(msg.id))
+                .set(routes::active.eq(msg.active))
+                .get_result::<Route>(&conn)?;
+            record_version(&conn, &route, Some(msg.changed_by), None)?;
+            let audit = msg
+                .audit
+                .by_for(msg.changed_by, before.creator_id)
+                .before(&AdminRouteInfo::from(before))
+                .after(&AdminRouteInfo::from(route.clone()));
+            record_in(&conn, audit)?;
+            Ok(route)
+        })
+    }
+}
+
+impl Handler<GetSystemStats> for DbActor {
+    type Result = QueryResult<SystemStats>;
+
+    fn handle(&mut self, _: GetSystemStats, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let day_ago = Utc::now().naive_utc() - Duration::days(1);
+
+        Ok(SystemStats {
+            users: users::table.count().get_result(&conn)?,
+            active_users: users::table
+                .filter(users::active.eq(true))
+                .count()
+                .get_result(&conn)?,
+            suspended_users: users::table
+                .filter(users::suspended_at.is_not_null())
+                .count()
+                .get_result(&conn)?,
+            admins: users::table
+                .filter(users::role.eq(ROLE_ADMIN))
+                .count()
+                .get_result(&conn)?,
+            routes: routes::table.count().get_result(&conn)?,
+            active_routes: routes::table
+                .filter(routes::active.eq(true))
+                .count()
+                .get_result(&conn)?,
+            visits: routes::table
+                // SUM of BIGINT is NUMERIC in postgres
+                .select(sql::<BigInt>("COALESCE(SUM(visits), 0)::BIGINT"))
+                .get_result(&conn)?,
+            clicks_last_24h: clicks::table
+                .filter(clicks::clicked_at.ge(day_ago))
+                .count()
+                .get_result(&conn)?,
+            sessions_last_24h: user_sessions::table
+                .filter(user_sessions::last_seen_at.ge(day_ago))
+                .count()
+                .get_result(&conn)?,
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::api_tokens::{ApiToken, NewApiToken};
+use crate::schema::api_tokens::dsl::*;
+use crate::schema::users;
+use chrono::{Duration, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<ApiToken>")]
+pub struct CreateApiToken {
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<ApiToken>>")]
+pub struct GetMyApiTokens {
+    pub user_id: Uuid,
+}
+
+/// Looks up a token that has not expired and marks it as used
+#[derive(Message)]
+#[rtype(result = "QueryResult<ApiToken>")]
+pub struct UseApiToken {
+    pub token_hash: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<ApiToken>")]
+pub struct DeleteApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+
+impl Handler<CreateApiToken> for DbActor {
+    type Result = QueryResult<ApiToken>;
+
+    fn handle(&mut self, msg: CreateApiToken, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let new_token = NewApiToken {
+            user_id: msg.user_id,
+            name: msg.name,
+            token_hash: msg.token_hash,
+            scopes: msg.scopes,
+            expires_at: msg.expires_at,
+        };
+
+        diesel::insert_into(api_tokens)
+            .values(new_token)
+            .get_result::<ApiToken>(&conn)
+    }
+}
+
+impl Handler<GetMyApiTokens> for DbActor {
+    type Result = QueryResult<Vec<ApiToken>>;
+
+    fn handle(&mut self, msg: GetMyApiTokens, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        api_tokens
+            .filter(user_id.eq(msg.user_id))
+            .order(created_at.desc())
+            .load(&conn)
+    }
+}
+
+impl Handler<UseApiToken> for DbActor {
+    type Result = QueryResult<ApiToken>;
+
+    fn handle(&mut self, msg: UseApiToken, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let now = Utc::now().naive_utc();
+
+        let token = api_tokens
+            .filter(token_hash.eq(msg.token_hash))
+            .filter(expires_at.is_null().or(expires_at.gt(now)))
+            .get_result::<ApiToken>(&conn)?;
+
+        // tokens of deactivated or suspended accounts stop working with them
+        let owner_active = users::table
+            .find(token.user_id)
+            .select(users::active)
+            .get_result::<bool>(&conn)?;
+        if !owner_active {
+            return Err(diesel::result::Error::NotFound);
+        }
+
+        // a token used in a tight loop shouldn't write on every request
+        if token
+            .last_used_at
+            .is_none_or(|used| now - used > Duration::minutes(1))
+        {
+            diesel::update(api_tokens.filter(id.eq(token.id)))
+                .set(last_used_at.eq(now))
+                .execute(&conn)?;
+        }
+        Ok(token)
+    }
+}
+
+impl Handler<DeleteApiToken> for DbActor {
+    type Result = QueryResult<ApiToken>;
+
+    fn handle(&mut self, msg: DeleteApiToken, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::delete(api_tokens)
+            .filter(id.eq(msg.id))
+            .filter(user_id.eq(msg.user_id))
+            .get_result::<ApiToken>(&conn)
+    }
+}
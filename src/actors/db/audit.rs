@@ -0,0 +1,65 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::audit::{AuditEvent, NewAuditEvent};
+use crate::schema::audit_events::dsl::*;
+use uuid::Uuid;
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<AuditEvent>")]
+pub struct RecordAuditEvent {
+    pub event: NewAuditEvent,
+}
+
+/// Newest first, optionally only those of one actor, in one user's history or about one target
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<AuditEvent>>")]
+pub struct GetAuditEvents {
+    pub actor_id: Option<Uuid>,
+    pub user_id: Option<Uuid>,
+    pub target_id: Option<Uuid>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Handler<RecordAuditEvent> for DbActor {
+    type Result = QueryResult<AuditEvent>;
+
+    fn handle(&mut self, msg: RecordAuditEvent, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        record_in(&conn, msg.event)
+    }
+}
+
+/// Writes `event` inside the transaction of the change it describes, so the change
+/// is rolled back when its audit row can't be written
+pub(crate) fn record_in(conn: &PgConnection, event: NewAuditEvent) -> QueryResult<AuditEvent> {
+    diesel::insert_into(audit_events)
+        .values(event)
+        .get_result::<AuditEvent>(conn)
+}
+
+impl Handler<GetAuditEvents> for DbActor {
+    type Result = QueryResult<Vec<AuditEvent>>;
+
+    fn handle(&mut self, msg: GetAuditEvents, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        let mut query = audit_events.into_boxed();
+        if let Some(actor) = msg.actor_id {
+            query = query.filter(actor_id.eq(actor));
+        }
+        if let Some(user) = msg.user_id {
+            query = query.filter(user_id.eq(user));
+        }
+        if let Some(target) = msg.target_id {
+            query = query.filter(target_id.eq(target));
+        }
+        query
+            .order((created_at.desc(), id.desc()))
+            .limit(msg.limit)
+            .offset(msg.offset)
+            .load(&conn)
+    }
+}
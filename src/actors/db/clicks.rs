@@ -0,0 +1,183 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::clicks::{ClickBucket, ClickCount, NewClick, RouteStats, StatsBucket};
+use crate::models::route_targets::VariantCount;
+use crate::schema::clicks::dsl::{clicked_at, clicks, ip_hash, route_id};
+use crate::schema::routes::dsl::{creator_id, id, routes, unique_visits, visits};
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Text, Timestamp, Uuid as SqlUuid};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Bind parameters Postgres accepts in a single statement
+const MAX_BIND_PARAMS: usize = 65_535;
+/// Columns of `NewClick`, each inserted click takes that many parameters
+const CLICK_COLUMNS: usize = 9;
+/// Clicks written per INSERT, larger batches are split
+pub const CLICKS_PER_INSERT: usize = MAX_BIND_PARAMS / CLICK_COLUMNS;
+
+/// Writes the clicks and counts them on their routes, all or nothing
+#[derive(Message)]
+#[rtype(result = "Result<(), UnrecordedClicks>")]
+pub struct RecordClicks {
+    pub clicks: Vec<NewClick>,
+}
+
+/// Why RecordClicks failed, with its clicks handed back so they can be retried
+pub struct UnrecordedClicks {
+    pub error: diesel::result::Error,
+    pub clicks: Vec<NewClick>,
+}
+
+impl Handler<RecordClicks> for DbActor {
+    type Result = Result<(), UnrecordedClicks>;
+
+    fn handle(&mut self, msg: RecordClicks, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        let result = conn.transaction(|| {
+            // later chunks see the clicks of earlier ones, unique counts stay exact
+            for chunk in msg.clicks.chunks(CLICKS_PER_INSERT) {
+                // (total visits, hashed IPs) per route in this chunk, clicks without
+                // an IP can't be told apart and only count as visits
+                let mut per_route: HashMap<Uuid, (i64, HashSet<&String>)> = HashMap::new();
+                for click in chunk {
+                    let entry = per_route.entry(click.route_id).or_default();
+                    entry.0 += 1;
+                    if let Some(hash) = &click.ip_hash {
+                        entry.1.insert(hash);
+                    }
+                }
+
+                for (r_id, (total, hashes)) in &per_route {
+                    // a visitor is unique until we have seen their hashed IP on this route
+                    let seen = clicks
+                        .filter(route_id.eq(r_id))
+                        .filter(ip_hash.eq_any(hashes.iter().copied()))
+                        .select(ip_hash)
+                        .distinct()
+                        .load::<Option<String>>(&conn)?
+                        .len() as i64;
+                    let unique = hashes.len() as i64 - seen;
+
+                    diesel::update(routes.filter(id.eq(r_id)))
+                        .set((
+                            visits.eq(visits + total),
+                            unique_visits.eq(unique_visits + unique),
+                        ))
+                        .execute(&conn)?;
+                }
+
+                diesel::insert_into(clicks).values(chunk).execute(&conn)?;
+            }
+            Ok(())
+        });
+        result.map_err(|error| UnrecordedClicks {
+            error,
+            clicks: msg.clicks,
+        })
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<RouteStats>")]
+pub struct GetRouteStats {
+    pub route_id: Uuid,
+    /// only the creator may read a route's stats
+    pub creator_id: Uuid,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub bucket: StatsBucket,
+    /// length of each top-N list
+    pub limit: i64,
+}
+
+/// Host of the referring page, so clicks from different pages of a site add up
+const REFERRER_HOST: &str =
+    "lower(substring(referrer from '^[a-zA-Z][a-zA-Z0-9+.-]*://([^/:?#]+)'))";
+
+impl Handler<GetRouteStats> for DbActor {
+    type Result = QueryResult<RouteStats>;
+
+    fn handle(&mut self, msg: GetRouteStats, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        // NotFound unless the route exists and belongs to the user
+        routes
+            .filter(id.eq(msg.route_id))
+            .filter(creator_id.eq(msg.creator_id))
+            .select(id)
+            .get_result::<Uuid>(&conn)?;
+
+        let total = clicks
+            .filter(route_id.eq(msg.route_id))
+            .filter(clicked_at.ge(msg.from))
+            .filter(clicked_at.lt(msg.to))
+            .count()
+            .get_result::<i64>(&conn)?;
+
+        let series = diesel::sql_query(
+            "SELECT series.bucket AS bucket, COUNT(clicks.id) AS clicks \
+             FROM generate_series(date_trunc($1, $3), $4 - interval '1 microsecond', \
+                                  ('1 ' || $1)::interval) AS series(bucket) \
+             LEFT JOIN clicks \
+                ON date_trunc($1, clicks.clicked_at) = series.bucket \
+                AND clicks.route_id = $2 \
+                AND clicks.clicked_at >= $3 AND clicks.clicked_at < $4 \
+             GROUP BY series.bucket \
+             ORDER BY series.bucket",
+        )
+        .bind::<Text, _>(msg.bucket.as_str())
+        .bind::<SqlUuid, _>(msg.route_id)
+        .bind::<Timestamp, _>(msg.from)
+        .bind::<Timestamp, _>(msg.to)
+        .load::<ClickBucket>(&conn)?;
+
+        // `column` is always one of our own expressions, never user input
+        let top = |column: &str| {
+            diesel::sql_query(format!(
+                "SELECT {} AS name, COUNT(*) AS clicks FROM clicks \
+                 WHERE route_id = $1 AND clicked_at >= $2 AND clicked_at < $3 \
+                 GROUP BY 1 ORDER BY 2 DESC, 1 LIMIT $4",
+                column
+            ))
+            .bind::<SqlUuid, _>(msg.route_id)
+            .bind::<Timestamp, _>(msg.from)
+            .bind::<Timestamp, _>(msg.to)
+            .bind::<BigInt, _>(msg.limit)
+            .load::<ClickCount>(&conn)
+        };
+
+        // every variant shows up, including those without clicks in the range
+        let variants = diesel::sql_query(
+            "SELECT route_targets.id AS variant_id, route_targets.label, route_targets.target, \
+                    COUNT(clicks.id) AS clicks, COUNT(DISTINCT clicks.ip_hash) AS visitors \
+             FROM route_targets \
+             LEFT JOIN clicks \
+                ON clicks.variant_id = route_targets.id \
+                AND clicks.clicked_at >= $2 AND clicks.clicked_at < $3 \
+             WHERE route_targets.route_id = $1 \
+             GROUP BY route_targets.id \
+             ORDER BY route_targets.created_at, route_targets.id",
+        )
+        .bind::<SqlUuid, _>(msg.route_id)
+        .bind::<Timestamp, _>(msg.from)
+        .bind::<Timestamp, _>(msg.to)
+        .load::<VariantCount>(&conn)?;
+
+        Ok(RouteStats {
+            route_id: msg.route_id,
+            from: msg.from,
+            to: msg.to,
+            bucket: msg.bucket,
+            total,
+            series,
+            referrers: top(REFERRER_HOST)?,
+            browsers: top("browser")?,
+            operating_systems: top("os")?,
+            countries: top("country")?,
+            variants,
+        })
+    }
+}
@@ -10,5 +10,16 @@ impl Actor for DbActor {
     type Context = SyncContext<Self>;
 }
 
+pub mod admin;
+pub mod api_tokens;
+pub mod audit;
+pub mod clicks;
+pub mod password_resets;
+pub mod route_schedules;
+pub mod route_targets;
+pub mod route_versions;
 pub mod routes;
+pub mod two_factor;
+pub mod user_sessions;
 pub mod users;
+pub mod verifications;
@@ -0,0 +1,112 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::password_resets::{NewPasswordReset, PasswordReset};
+use crate::models::users::User;
+use crate::schema::password_resets::dsl::*;
+use crate::schema::{api_tokens, user_sessions, users};
+use chrono::{Duration, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+/// Minimum time between two reset mails for the same user
+const RESEND_INTERVAL_SECS: i64 = 60;
+
+/// Replaces any unused reset token of the user with a new one.
+/// Ok(None) when the previous token was sent too recently
+#[derive(Message)]
+#[rtype(result = "QueryResult<Option<PasswordReset>>")]
+pub struct CreatePasswordReset {
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Sets a new password with an unused, unexpired token, logs the user out
+/// everywhere and revokes their API tokens. Ok(None) when the token is not valid
+#[derive(Message)]
+#[rtype(result = "QueryResult<Option<User>>")]
+pub struct CompletePasswordReset {
+    pub token_hash: String,
+    pub password_hash: String,
+}
+
+impl Handler<CreatePasswordReset> for DbActor {
+    type Result = QueryResult<Option<PasswordReset>>;
+
+    fn handle(&mut self, msg: CreatePasswordReset, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let throttled_since = Utc::now().naive_utc() - Duration::seconds(RESEND_INTERVAL_SECS);
+
+        conn.transaction(|| {
+            let recent = password_resets
+                .filter(user_id.eq(msg.user_id))
+                .filter(created_at.gt(throttled_since))
+                .count()
+                .get_result::<i64>(&conn)?;
+            if recent > 0 {
+                return Ok(None);
+            }
+
+            diesel::delete(
+                password_resets
+                    .filter(user_id.eq(msg.user_id))
+                    .filter(used_at.is_null()),
+            )
+            .execute(&conn)?;
+            diesel::insert_into(password_resets)
+                .values(NewPasswordReset {
+                    user_id: msg.user_id,
+                    token_hash: msg.token_hash,
+                    expires_at: msg.expires_at,
+                })
+                .get_result::<PasswordReset>(&conn)
+                .map(Some)
+        })
+    }
+}
+
+impl Handler<CompletePasswordReset> for DbActor {
+    type Result = QueryResult<Option<User>>;
+
+    fn handle(&mut self, msg: CompletePasswordReset, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let now = Utc::now().naive_utc();
+
+        conn.transaction(|| {
+            // claiming the token first makes a second use of it find nothing
+            let reset = diesel::update(
+                password_resets
+                    .filter(token_hash.eq(msg.token_hash))
+                    .filter(used_at.is_null())
+                    .filter(expires_at.gt(now)),
+            )
+            .set(used_at.eq(now))
+            .get_result::<PasswordReset>(&conn)
+            .optional()?;
+
+            let reset = match reset {
+                Some(reset) => reset,
+                None => return Ok(None),
+            };
+
+            diesel::delete(
+                password_resets
+                    .filter(user_id.eq(reset.user_id))
+                    .filter(used_at.is_null()),
+            )
+            .execute(&conn)?;
+            diesel::delete(user_sessions::table.filter(user_sessions::user_id.eq(reset.user_id)))
+                .execute(&conn)?;
+            // whoever knew the old password may have minted tokens with it
+            diesel::delete(api_tokens::table.filter(api_tokens::user_id.eq(reset.user_id)))
+                .execute(&conn)?;
+            diesel::update(users::table.find(reset.user_id))
+                .set((
+                    users::password_hash.eq(msg.password_hash),
+                    users::session_epoch.eq(users::session_epoch + 1),
+                ))
+                .get_result::<User>(&conn)
+                .map(Some)
+        })
+    }
+}
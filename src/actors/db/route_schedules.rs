@@ -0,0 +1,208 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::audit::record_in;
+use crate::actors::db::route_versions::record_version;
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::audit::{NewAuditEvent, ACTION_ROUTE_SCHEDULE_APPLY, TARGET_ROUTE};
+use crate::models::route_schedules::{NewRouteSchedule, RouteSchedule};
+use crate::models::routes::Route;
+use crate::schema::{route_schedules, routes};
+use chrono::NaiveDateTime;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Routes handled by a single ApplyDueSchedules, the rest wait for the next run
+const APPLY_BATCH_SIZE: i64 = 500;
+
+/// NotFound unless `creator_id` owns the route
+#[derive(Message)]
+#[rtype(result = "QueryResult<RouteSchedule>")]
+pub struct CreateRouteSchedule {
+    pub creator_id: Uuid,
+    pub schedule: NewRouteSchedule,
+}
+
+/// Pending and applied schedules of a route by effective_at, NotFound unless
+/// `creator_id` owns it
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<RouteSchedule>>")]
+pub struct GetRouteSchedules {
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+}
+
+/// Cancels a pending schedule, applied and superseded ones are history and stay
+#[derive(Message)]
+#[rtype(result = "QueryResult<RouteSchedule>")]
+pub struct DeleteRouteSchedule {
+    pub id: Uuid,
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+}
+
+/// Writes the target of the latest schedule due at `now` to its route, earlier due
+/// ones of the same route are superseded. Returns how many schedules were applied
+#[derive(Message)]
+#[rtype(result = "QueryResult<usize>")]
+pub struct ApplyDueSchedules {
+    pub now: NaiveDateTime,
+}
+
+fn owned_route(conn: &PgConnection, route_id: Uuid, creator_id: Uuid) -> QueryResult<Route> {
+    routes::table
+        .filter(routes::id.eq(route_id))
+        .filter(routes::creator_id.eq(creator_id))
+        .get_result(conn)
+}
+
+/// Target of the latest schedule of `route_id` due at `now` and not applied or
+/// superseded yet
+pub(crate) fn due_target(
+    conn: &PgConnection,
+    route_id: Uuid,
+    now: NaiveDateTime,
+) -> QueryResult<Option<String>> {
+    route_schedules::table
+        .filter(route_schedules::route_id.eq(route_id))
+        .filter(route_schedules::applied_at.is_null())
+        .filter(route_schedules::superseded_at.is_null())
+        .filter(route_schedules::effective_at.le(now))
+        .order(route_schedules::effective_at.desc())
+        .select(route_schedules::target)
+        .first(conn)
+        .optional()
+}
+
+/// Marks the schedules of `route_id` due at `now` and not applied yet as superseded,
+/// call in the transaction that changes the route's target by hand so the next
+/// ApplyDueSchedules doesn't overwrite it
+pub(crate) fn supersede_due(
+    conn: &PgConnection,
+    route_id: Uuid,
+    now: NaiveDateTime,
+) -> QueryResult<usize> {
+    diesel::update(
+        route_schedules::table
+            .filter(route_schedules::route_id.eq(route_id))
+            .filter(route_schedules::applied_at.is_null())
+            .filter(route_schedules::superseded_at.is_null())
+            .filter(route_schedules::effective_at.le(now)),
+    )
+    .set(route_schedules::superseded_at.eq(now))
+    .execute(conn)
+}
+
+impl Handler<CreateRouteSchedule> for DbActor {
+    type Result = QueryResult<RouteSchedule>;
+
+    fn handle(&mut self, msg: CreateRouteSchedule, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        owned_route(&conn, msg.schedule.route_id, msg.creator_id)?;
+        diesel::insert_into(route_schedules::table)
+            .values(msg.schedule)
+            .get_result(&conn)
+    }
+}
+
+impl Handler<GetRouteSchedules> for DbActor {
+    type Result = QueryResult<Vec<RouteSchedule>>;
+
+    fn handle(&mut self, msg: GetRouteSchedules, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        owned_route(&conn, msg.route_id, msg.creator_id)?;
+        route_schedules::table
+            .filter(route_schedules::route_id.eq(msg.route_id))
+            .order(route_schedules::effective_at)
+            .load(&conn)
+    }
+}
+
+impl Handler<DeleteRouteSchedule> for DbActor {
+    type Result = QueryResult<RouteSchedule>;
+
+    fn handle(&mut self, msg: DeleteRouteSchedule, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        owned_route(&conn, msg.route_id, msg.creator_id)?;
+        diesel::delete(
+            route_schedules::table
+                .filter(route_schedules::id.eq(msg.id))
+                .filter(route_schedules::route_id.eq(msg.route_id))
+                .filter(route_schedules::applied_at.is_null())
+                .filter(route_schedules::superseded_at.is_null()),
+        )
+        .get_result(&conn)
+    }
+}
+
+impl Handler<ApplyDueSchedules> for DbActor {
+    type Result = QueryResult<usize>;
+
+    fn handle(&mut self, msg: ApplyDueSchedules, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let due = || {
+                route_schedules::table
+                    .filter(route_schedules::applied_at.is_null())
+                    .filter(route_schedules::superseded_at.is_null())
+                    .filter(route_schedules::effective_at.le(msg.now))
+            };
+            // routes before their schedules, in the order UpdateRoute and RollbackRoute
+            // lock them. Routes locked by another run (or instance) are left to it
+            let locked = routes::table
+                .filter(routes::id.eq_any(due().select(route_schedules::route_id)))
+                .order(routes::id)
+                .limit(APPLY_BATCH_SIZE)
+                .for_update()
+                .skip_locked()
+                .load::<Route>(&conn)?;
+            let route_ids: Vec<Uuid> = locked.iter().map(|route| route.id).collect();
+            let schedules = due()
+                .filter(route_schedules::route_id.eq_any(&route_ids))
+                .order((route_schedules::route_id, route_schedules::effective_at))
+                .load::<RouteSchedule>(&conn)?;
+
+            let mut applied = Vec::new();
+            let mut superseded = Vec::new();
+            for before in locked {
+                let mut due: Vec<&RouteSchedule> = schedules
+                    .iter()
+                    .filter(|schedule| schedule.route_id == before.id)
+                    .collect();
+                // ordered by effective_at, so the last due schedule wins and the
+                // earlier ones were never in effect long enough to be applied
+                let schedule = match due.pop() {
+                    Some(schedule) => schedule,
+                    None => continue,
+                };
+                superseded.extend(due.iter().map(|schedule| schedule.id));
+                applied.push(schedule.id);
+
+                let route = diesel::update(routes::table.find(before.id))
+                    .set(routes::target.eq(&schedule.target))
+                    .get_result::<Route>(&conn)?;
+                record_version(&conn, &route, schedule.created_by, None)?;
+
+                let mut event =
+                    NewAuditEvent::new(ACTION_ROUTE_SCHEDULE_APPLY, TARGET_ROUTE, route.id)
+                        .details(json!({ "schedule_id": schedule.id }))
+                        .before(&before)
+                        .after(&route);
+                if let Some(owner) = route.creator_id {
+                    event = event.concerning(owner);
+                }
+                record_in(&conn, event)?;
+            }
+
+            diesel::update(route_schedules::table.filter(route_schedules::id.eq_any(&superseded)))
+                .set(route_schedules::superseded_at.eq(msg.now))
+                .execute(&conn)?;
+            diesel::update(route_schedules::table.filter(route_schedules::id.eq_any(&applied)))
+                .set(route_schedules::applied_at.eq(msg.now))
+                .execute(&conn)
+        })
+    }
+}
@@ -0,0 +1,158 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::route_versions::record_version;
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::route_targets::{NewRouteTarget, RouteTarget, MAX_VARIANTS};
+use crate::models::routes::Route;
+use crate::schema::{route_targets, routes};
+use diesel::PgConnection;
+use uuid::Uuid;
+
+/// Variants of a route, oldest first, NotFound unless `creator_id` owns it
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<RouteTarget>>")]
+pub struct GetRouteTargets {
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+}
+
+/// NotFound unless `creator_id` owns the route, None when the route already has
+/// MAX_VARIANTS variants
+#[derive(Message)]
+#[rtype(result = "QueryResult<Option<RouteTarget>>")]
+pub struct CreateRouteTarget {
+    pub creator_id: Uuid,
+    pub variant: NewRouteTarget,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<RouteTarget>")]
+pub struct UpdateRouteTarget {
+    pub id: Uuid,
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+    pub weight: i32,
+    pub label: Option<String>,
+}
+
+/// Clicks sent to the variant are kept, without it
+#[derive(Message)]
+#[rtype(result = "QueryResult<RouteTarget>")]
+pub struct DeleteRouteTarget {
+    pub id: Uuid,
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+}
+
+fn owned_route(conn: &PgConnection, route_id: Uuid, creator_id: Uuid) -> QueryResult<Route> {
+    routes::table
+        .filter(routes::id.eq(route_id))
+        .filter(routes::creator_id.eq(creator_id))
+        .get_result(conn)
+}
+
+/// Like `owned_route`, locked until the transaction ends so changes to the
+/// variants of one route happen one after the other
+fn lock_owned_route(conn: &PgConnection, route_id: Uuid, creator_id: Uuid) -> QueryResult<Route> {
+    routes::table
+        .filter(routes::id.eq(route_id))
+        .filter(routes::creator_id.eq(creator_id))
+        .for_update()
+        .get_result(conn)
+}
+
+/// Every variant of `route_id`, paused ones included, oldest first
+pub(crate) fn all_variants(conn: &PgConnection, route_id: Uuid) -> QueryResult<Vec<RouteTarget>> {
+    route_targets::table
+        .filter(route_targets::route_id.eq(route_id))
+        .order((route_targets::created_at, route_targets::id))
+        .load(conn)
+}
+
+/// Variants of `route_id` that get traffic, in the order `split::pick` relies on
+pub(crate) fn live_variants(conn: &PgConnection, route_id: Uuid) -> QueryResult<Vec<RouteTarget>> {
+    route_targets::table
+        .filter(route_targets::route_id.eq(route_id))
+        .filter(route_targets::weight.gt(0))
+        .order((route_targets::created_at, route_targets::id))
+        .load(conn)
+}
+
+impl Handler<GetRouteTargets> for DbActor {
+    type Result = QueryResult<Vec<RouteTarget>>;
+
+    fn handle(&mut self, msg: GetRouteTargets, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        owned_route(&conn, msg.route_id, msg.creator_id)?;
+        all_variants(&conn, msg.route_id)
+    }
+}
+
+impl Handler<CreateRouteTarget> for DbActor {
+    type Result = QueryResult<Option<RouteTarget>>;
+
+    fn handle(&mut self, msg: CreateRouteTarget, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let route = lock_owned_route(&conn, msg.variant.route_id, msg.creator_id)?;
+            let count = route_targets::table
+                .filter(route_targets::route_id.eq(route.id))
+                .count()
+                .get_result::<i64>(&conn)?;
+            if count >= MAX_VARIANTS as i64 {
+                return Ok(None);
+            }
+            let variant = diesel::insert_into(route_targets::table)
+                .values(msg.variant)
+                .get_result(&conn)?;
+            record_version(&conn, &route, Some(msg.creator_id), None)?;
+            Ok(Some(variant))
+        })
+    }
+}
+
+impl Handler<UpdateRouteTarget> for DbActor {
+    type Result = QueryResult<RouteTarget>;
+
+    fn handle(&mut self, msg: UpdateRouteTarget, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let route = lock_owned_route(&conn, msg.route_id, msg.creator_id)?;
+            let variant = diesel::update(
+                route_targets::table
+                    .filter(route_targets::id.eq(msg.id))
+                    .filter(route_targets::route_id.eq(msg.route_id)),
+            )
+            .set((
+                route_targets::weight.eq(msg.weight),
+                route_targets::label.eq(msg.label),
+            ))
+            .get_result(&conn)?;
+            record_version(&conn, &route, Some(msg.creator_id), None)?;
+            Ok(variant)
+        })
+    }
+}
+
+impl Handler<DeleteRouteTarget> for DbActor {
+    type Result = QueryResult<RouteTarget>;
+
+    fn handle(&mut self, msg: DeleteRouteTarget, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let route = lock_owned_route(&conn, msg.route_id, msg.creator_id)?;
+            let variant = diesel::delete(
+                route_targets::table
+                    .filter(route_targets::id.eq(msg.id))
+                    .filter(route_targets::route_id.eq(msg.route_id)),
+            )
+            .get_result(&conn)?;
+            record_version(&conn, &route, Some(msg.creator_id), None)?;
+            Ok(variant)
+        })
+    }
+}
@@ -0,0 +1,149 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::route_schedules::supersede_due;
+use crate::actors::db::route_targets::all_variants;
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::route_versions::{NewRouteVersion, RouteVersion};
+use crate::models::routes::Route;
+use crate::schema::{route_targets, route_versions, routes};
+use chrono::Utc;
+use diesel::PgConnection;
+use uuid::Uuid;
+
+/// Every version of a route, newest first, NotFound unless `creator_id` owns it
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<RouteVersion>>")]
+pub struct GetRouteHistory {
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+}
+
+/// One version of a route, NotFound unless `creator_id` owns it
+#[derive(Message)]
+#[rtype(result = "QueryResult<RouteVersion>")]
+pub struct GetRouteVersion {
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+    pub version: i32,
+}
+
+/// Restores a route to an older version, which is recorded as a new version.
+/// Variants get their weight and label back, ones added since are paused and
+/// deleted ones stay deleted
+#[derive(Message)]
+#[rtype(result = "QueryResult<Route>")]
+pub struct RollbackRoute {
+    pub route_id: Uuid,
+    pub creator_id: Uuid,
+    pub version: i32,
+}
+
+/// Records `route` and its variants as they are now as its next version, call in
+/// the transaction that changed them so versions follow the order of the changes
+pub(crate) fn record_version(
+    conn: &PgConnection,
+    route: &Route,
+    changed_by: Option<Uuid>,
+    rolled_back_from: Option<i32>,
+) -> QueryResult<RouteVersion> {
+    let latest = route_versions::table
+        .filter(route_versions::route_id.eq(route.id))
+        .select(diesel::dsl::max(route_versions::version))
+        .get_result::<Option<i32>>(conn)?;
+    let variants = all_variants(conn, route.id)?;
+
+    let mut version = NewRouteVersion::of(route, &variants, latest.unwrap_or(0) + 1, changed_by);
+    version.rolled_back_from = rolled_back_from;
+    diesel::insert_into(route_versions::table)
+        .values(version)
+        .get_result(conn)
+}
+
+fn owned_version(
+    conn: &PgConnection,
+    route_id: Uuid,
+    creator_id: Uuid,
+    version: i32,
+) -> QueryResult<RouteVersion> {
+    route_versions::table
+        .inner_join(routes::table)
+        .filter(route_versions::route_id.eq(route_id))
+        .filter(route_versions::version.eq(version))
+        .filter(routes::creator_id.eq(creator_id))
+        .select(route_versions::all_columns)
+        .get_result(conn)
+}
+
+impl Handler<GetRouteHistory> for DbActor {
+    type Result = QueryResult<Vec<RouteVersion>>;
+
+    fn handle(&mut self, msg: GetRouteHistory, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        routes::table
+            .filter(routes::id.eq(msg.route_id))
+            .filter(routes::creator_id.eq(msg.creator_id))
+            .get_result::<Route>(&conn)?;
+        route_versions::table
+            .filter(route_versions::route_id.eq(msg.route_id))
+            .order(route_versions::version.desc())
+            .load(&conn)
+    }
+}
+
+impl Handler<GetRouteVersion> for DbActor {
+    type Result = QueryResult<RouteVersion>;
+
+    fn handle(&mut self, msg: GetRouteVersion, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        owned_version(&conn, msg.route_id, msg.creator_id, msg.version)
+    }
+}
+
+impl Handler<RollbackRoute> for DbActor {
+    type Result = QueryResult<Route>;
+
+    fn handle(&mut self, msg: RollbackRoute, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let old = owned_version(&conn, msg.route_id, msg.creator_id, msg.version)?;
+            let previous = routes::table
+                .find(msg.route_id)
+                .select(routes::target)
+                .for_update()
+                .get_result::<String>(&conn)?;
+            let route = diesel::update(routes::table.find(msg.route_id))
+                .set((
+                    routes::slug.eq(old.slug),
+                    routes::target.eq(old.target),
+                    routes::active.eq(old.active),
+                    routes::active_from.eq(old.active_from),
+                    routes::active_till.eq(old.active_till),
+                    routes::rules.eq(old.rules),
+                ))
+                .get_result::<Route>(&conn)?;
+            if let Some(old_variants) = old.variants {
+                for variant in all_variants(&conn, route.id)? {
+                    let (weight, label) =
+                        match old_variants.0.iter().find(|old| old.id == variant.id) {
+                            Some(old) => (old.weight, old.label.clone()),
+                            None => (0, variant.label),
+                        };
+                    diesel::update(route_targets::table.find(variant.id))
+                        .set((
+                            route_targets::weight.eq(weight),
+                            route_targets::label.eq(label),
+                        ))
+                        .execute(&conn)?;
+                }
+            }
+            if route.target != previous {
+                supersede_due(&conn, route.id, Utc::now().naive_utc())?;
+            }
+            record_version(&conn, &route, Some(msg.creator_id), Some(msg.version))?;
+            Ok(route)
+        })
+    }
+}
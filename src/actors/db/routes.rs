@@ -1,10 +1,17 @@
 use crate::actix::{Handler, Message};
 use crate::diesel::prelude::*;
-use crate::models::routes::{NewRoute, Route};
+use crate::models::route_rules::RouteRules;
+use crate::models::routes::{NewRoute, Route, RouteLookup};
 use crate::schema::routes;
 use crate::schema::routes::dsl::*;
+use crate::schema::users;
+use crate::utils::reserved::is_reserved;
+use chrono::{NaiveDateTime, Utc};
 use uuid::Uuid;
 
+use crate::actors::db::route_schedules::{due_target, supersede_due};
+use crate::actors::db::route_targets::live_variants;
+use crate::actors::db::route_versions::record_version;
 use crate::actors::db::DbActor;
 
 #[derive(Message)]
@@ -14,23 +21,33 @@ pub struct CreateRoute {
     pub creator_id: Option<Uuid>,
     pub target: String,
     pub active: Option<bool>,
+    pub active_from: Option<NaiveDateTime>,
+    pub active_till: Option<NaiveDateTime>,
+    pub rules: RouteRules,
 }
 
+/// The route with what decides where it redirects to at `now`
 #[derive(Message)]
-#[rtype(result = "QueryResult<Route>")]
+#[rtype(result = "QueryResult<RouteLookup>")]
 pub struct ReadRouteBySlug {
     pub slug: String,
+    pub now: NaiveDateTime,
 }
 
+// update replaces the whole route, so a missing window clears it
 #[derive(Message, AsChangeset)]
 #[rtype(result = "QueryResult<Route>")]
 #[table_name = "routes"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct UpdateRoute {
     pub id: Uuid,
     pub slug: String,
     pub creator_id: Uuid,
     pub target: String,
     pub active: bool,
+    pub active_from: Option<NaiveDateTime>,
+    pub active_till: Option<NaiveDateTime>,
+    pub rules: RouteRules,
 }
 
 #[derive(Message)]
@@ -56,7 +73,6 @@ pub struct GetMyRoutes {
 pub struct RouteSlugAvailable {
     pub slug: String,
 }
-// TODO: Increment visit and increment unique visit
 
 // #[derive(Message)]
 // #[rtype(result = "QueryResult<Route>")]
@@ -78,20 +94,40 @@ impl Handler<CreateRoute> for DbActor {
             creator_id: msg.creator_id,
             target: msg.target,
             active: msg.active,
+            active_from: msg.active_from,
+            active_till: msg.active_till,
+            rules: msg.rules,
         };
 
-        diesel::insert_into(routes)
-            .values(new_route)
-            .get_result::<Route>(&conn)
+        conn.transaction(|| {
+            let route = diesel::insert_into(routes)
+                .values(new_route)
+                .get_result::<Route>(&conn)?;
+            record_version(&conn, &route, route.creator_id, None)?;
+            Ok(route)
+        })
     }
 }
 
 impl Handler<ReadRouteBySlug> for DbActor {
-    type Result = QueryResult<Route>;
+    type Result = QueryResult<RouteLookup>;
     fn handle(&mut self, msg: ReadRouteBySlug, _: &mut Self::Context) -> Self::Result {
         let conn = self.0.get().expect("Unable to get a connection");
 
-        routes.filter(slug.eq(msg.slug)).get_result::<Route>(&conn)
+        let (route, owner_active) = routes
+            .left_join(users::table)
+            .filter(slug.eq(msg.slug))
+            .select((routes::all_columns, users::active.nullable()))
+            .get_result::<(Route, Option<bool>)>(&conn)?;
+        let scheduled_target = due_target(&conn, route.id, msg.now)?;
+        let variants = live_variants(&conn, route.id)?;
+
+        Ok(RouteLookup {
+            route,
+            owner_active,
+            scheduled_target,
+            variants,
+        })
     }
 }
 
@@ -119,11 +155,24 @@ impl Handler<UpdateRoute> for DbActor {
     fn handle(&mut self, msg: UpdateRoute, _: &mut Self::Context) -> Self::Result {
         let conn = self.0.get().expect("Unable to get a connection");
 
-        diesel::update(routes)
-            .filter(id.eq(msg.id))
-            .filter(creator_id.eq(msg.creator_id))
-            .set(msg)
-            .get_result::<Route>(&conn)
+        let changed_by = msg.creator_id;
+        conn.transaction(|| {
+            let previous = routes
+                .find(msg.id)
+                .select(target)
+                .for_update()
+                .get_result::<String>(&conn)?;
+            let route = diesel::update(routes)
+                .filter(id.eq(msg.id))
+                .filter(creator_id.eq(msg.creator_id))
+                .set(&msg)
+                .get_result::<Route>(&conn)?;
+            if route.target != previous {
+                supersede_due(&conn, route.id, Utc::now().naive_utc())?;
+            }
+            record_version(&conn, &route, Some(changed_by), None)?;
+            Ok(route)
+        })
     }
 }
 
@@ -144,6 +193,9 @@ impl Handler<RouteSlugAvailable> for DbActor {
     type Result = bool;
 
     fn handle(&mut self, msg: RouteSlugAvailable, _: &mut Self::Context) -> Self::Result {
+        if is_reserved(&msg.slug) {
+            return false;
+        }
         let conn = self.0.get().expect("Unable to get a connection");
 
         routes
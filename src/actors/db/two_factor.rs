@@ -0,0 +1,135 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::users::User;
+use crate::schema::users::dsl::*;
+use diesel::sql_types::{Text, Uuid as SqlUuid};
+use uuid::Uuid;
+
+/// Stores a new, not yet confirmed secret. Fails with NotFound once 2FA is enabled
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct SetTotpSecret {
+    pub user_id: Uuid,
+    pub secret: Vec<u8>,
+}
+
+/// Turns 2FA on after the first code was confirmed
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct EnableTotp {
+    pub user_id: Uuid,
+    /// step of the code used to confirm, it can't be used again to log in
+    pub step: i64,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct DisableTotp {
+    pub user_id: Uuid,
+}
+
+/// Records that a code of `step` was used, false when that step or a later one
+/// was used before
+#[derive(Message)]
+#[rtype(result = "QueryResult<bool>")]
+pub struct UseTotpStep {
+    pub user_id: Uuid,
+    pub step: i64,
+}
+
+/// Removes a recovery code, false when the user has no such unused code
+#[derive(Message)]
+#[rtype(result = "QueryResult<bool>")]
+pub struct UseRecoveryCode {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
+
+impl Handler<SetTotpSecret> for DbActor {
+    type Result = QueryResult<User>;
+
+    fn handle(&mut self, msg: SetTotpSecret, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::update(
+            users
+                .filter(id.eq(msg.user_id))
+                .filter(totp_enabled.eq(false)),
+        )
+        .set(totp_secret.eq(msg.secret))
+        .get_result::<User>(&conn)
+    }
+}
+
+impl Handler<EnableTotp> for DbActor {
+    type Result = QueryResult<User>;
+
+    fn handle(&mut self, msg: EnableTotp, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::update(
+            users
+                .filter(id.eq(msg.user_id))
+                .filter(totp_secret.is_not_null()),
+        )
+        .set((
+            totp_enabled.eq(true),
+            totp_last_step.eq(msg.step),
+            recovery_codes.eq(msg.recovery_codes),
+        ))
+        .get_result::<User>(&conn)
+    }
+}
+
+impl Handler<DisableTotp> for DbActor {
+    type Result = QueryResult<User>;
+
+    fn handle(&mut self, msg: DisableTotp, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::update(users.filter(id.eq(msg.user_id)))
+            .set((
+                totp_secret.eq(None::<Vec<u8>>),
+                totp_enabled.eq(false),
+                totp_last_step.eq(0),
+                recovery_codes.eq(Vec::<String>::new()),
+            ))
+            .get_result::<User>(&conn)
+    }
+}
+
+impl Handler<UseTotpStep> for DbActor {
+    type Result = QueryResult<bool>;
+
+    fn handle(&mut self, msg: UseTotpStep, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::update(
+            users
+                .filter(id.eq(msg.user_id))
+                .filter(totp_last_step.lt(msg.step)),
+        )
+        .set(totp_last_step.eq(msg.step))
+        .execute(&conn)
+        .map(|updated| updated == 1)
+    }
+}
+
+impl Handler<UseRecoveryCode> for DbActor {
+    type Result = QueryResult<bool>;
+
+    fn handle(&mut self, msg: UseRecoveryCode, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::sql_query(
+            "UPDATE users SET recovery_codes = array_remove(recovery_codes, $1) \
+             WHERE id = $2 AND $1 = ANY(recovery_codes)",
+        )
+        .bind::<Text, _>(msg.code_hash)
+        .bind::<SqlUuid, _>(msg.user_id)
+        .execute(&conn)
+        .map(|updated| updated == 1)
+    }
+}
@@ -0,0 +1,136 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::user_sessions::{NewUserSession, UserSession};
+use crate::schema::user_sessions::dsl::*;
+use crate::schema::users;
+use chrono::{Duration, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+/// Also prunes the user's sessions that expired in the session store
+#[derive(Message)]
+#[rtype(result = "QueryResult<UserSession>")]
+pub struct CreateUserSession {
+    pub session: NewUserSession,
+    /// sessions last seen before this are gone from the session store
+    pub expired_before: NaiveDateTime,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<Vec<UserSession>>")]
+pub struct GetMyUserSessions {
+    pub user_id: Uuid,
+    pub expired_before: NaiveDateTime,
+}
+
+/// Looks up a session that was not revoked or expired and marks it as seen
+#[derive(Message)]
+#[rtype(result = "QueryResult<UserSession>")]
+pub struct TouchUserSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub expired_before: NaiveDateTime,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<UserSession>")]
+pub struct DeleteUserSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+
+/// Logs the user out everywhere, including sessions from before sessions were
+/// tracked, by deleting every session and moving to a new session epoch
+#[derive(Message)]
+#[rtype(result = "QueryResult<usize>")]
+pub struct DeleteAllUserSessions {
+    pub user_id: Uuid,
+}
+
+impl Handler<CreateUserSession> for DbActor {
+    type Result = QueryResult<UserSession>;
+
+    fn handle(&mut self, msg: CreateUserSession, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            diesel::delete(
+                user_sessions
+                    .filter(user_id.eq(msg.session.user_id))
+                    .filter(last_seen_at.lt(msg.expired_before)),
+            )
+            .execute(&conn)?;
+            diesel::insert_into(user_sessions)
+                .values(msg.session)
+                .get_result::<UserSession>(&conn)
+        })
+    }
+}
+
+impl Handler<GetMyUserSessions> for DbActor {
+    type Result = QueryResult<Vec<UserSession>>;
+
+    fn handle(&mut self, msg: GetMyUserSessions, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        user_sessions
+            .filter(user_id.eq(msg.user_id))
+            .filter(last_seen_at.ge(msg.expired_before))
+            .order(last_seen_at.desc())
+            .load(&conn)
+    }
+}
+
+impl Handler<TouchUserSession> for DbActor {
+    type Result = QueryResult<UserSession>;
+
+    fn handle(&mut self, msg: TouchUserSession, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let now = Utc::now().naive_utc();
+
+        let session = user_sessions
+            .filter(id.eq(msg.id))
+            .filter(user_id.eq(msg.user_id))
+            .filter(last_seen_at.ge(msg.expired_before))
+            .get_result::<UserSession>(&conn)?;
+
+        // every request carries the cookie, don't write on each of them
+        if now - session.last_seen_at > Duration::minutes(1) {
+            diesel::update(user_sessions.filter(id.eq(session.id)))
+                .set(last_seen_at.eq(now))
+                .execute(&conn)?;
+        }
+        Ok(session)
+    }
+}
+
+impl Handler<DeleteUserSession> for DbActor {
+    type Result = QueryResult<UserSession>;
+
+    fn handle(&mut self, msg: DeleteUserSession, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::delete(user_sessions)
+            .filter(id.eq(msg.id))
+            .filter(user_id.eq(msg.user_id))
+            .get_result::<UserSession>(&conn)
+    }
+}
+
+impl Handler<DeleteAllUserSessions> for DbActor {
+    type Result = QueryResult<usize>;
+
+    fn handle(&mut self, msg: DeleteAllUserSessions, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| revoke_all_sessions(&conn, msg.user_id))
+    }
+}
+
+/// Logs `user` out everywhere, for use inside other transactions
+pub(crate) fn revoke_all_sessions(conn: &PgConnection, user: Uuid) -> QueryResult<usize> {
+    diesel::update(users::table.find(user))
+        .set(users::session_epoch.eq(users::session_epoch + 1))
+        .execute(conn)?;
+    diesel::delete(user_sessions.filter(user_id.eq(user))).execute(conn)
+}
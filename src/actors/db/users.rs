@@ -1,9 +1,15 @@
 use crate::actix::{Handler, Message};
+use crate::actors::db::audit::record_in;
+use crate::actors::db::user_sessions::revoke_all_sessions;
 use crate::actors::db::DbActor;
 use crate::diesel::prelude::*;
-use crate::models::users::{NewUser, User};
+use crate::models::audit::NewAuditEvent;
+use crate::models::users::{AuditUserInfo, NewUser, User};
 use crate::schema::users;
-use crate::schema::users::dsl::{email, id, username, users as users_q};
+use crate::schema::users::dsl::{
+    active, email, id, suspended_at, suspension_reason, username, users as users_q,
+};
+use chrono::Utc;
 
 use uuid::Uuid;
 
@@ -30,6 +36,12 @@ pub struct GetUserByUsername {
     pub username: String,
 }
 
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct GetUserByEmail {
+    pub email: String,
+}
+
 #[derive(Message)]
 #[rtype(result = "QueryResult<bool>")]
 pub struct CheckUserNameExists {
@@ -39,6 +51,7 @@ pub struct CheckUserNameExists {
 // Update messages
 
 // FIXME:
+/// A new password logs the user out everywhere
 #[derive(Message, AsChangeset)]
 #[rtype(result = "QueryResult<User>")]
 #[table_name = "users"]
@@ -48,6 +61,42 @@ pub struct UpdateUser {
     pub email: Option<String>,
     pub password_hash: Option<String>,
     pub username: Option<String>,
+    pub email_verified: Option<bool>,
+}
+
+/// Deactivated by the owner or an admin, logs them out everywhere
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct DeactivateUser {
+    pub id: Uuid,
+    /// admin event, written in the same transaction with the user as it was before
+    /// and is after
+    pub audit: Option<NewAuditEvent>,
+}
+
+/// Undoes DeactivateUser, NotFound for suspended accounts which only an admin can lift
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct ReactivateUser {
+    pub id: Uuid,
+}
+
+/// Suspended by an admin, logs the user out everywhere
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct SuspendUser {
+    pub id: Uuid,
+    pub reason: String,
+    /// written in the same transaction, see `DeactivateUser::audit`
+    pub audit: NewAuditEvent,
+}
+
+#[derive(Message)]
+#[rtype(result = "QueryResult<User>")]
+pub struct UnsuspendUser {
+    pub id: Uuid,
+    /// written in the same transaction, see `DeactivateUser::audit`
+    pub audit: NewAuditEvent,
 }
 
 // Delete messages
@@ -107,6 +156,17 @@ impl Handler<GetUserByUsername> for DbActor {
     }
 }
 
+impl Handler<GetUserByEmail> for DbActor {
+    type Result = QueryResult<User>;
+    fn handle(&mut self, msg: GetUserByEmail, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        users_q
+            .filter(email.eq(msg.email))
+            .get_result::<User>(&conn)
+    }
+}
+
 impl Handler<CheckUserNameExists> for DbActor {
     type Result = QueryResult<bool>;
     fn handle(&mut self, msg: CheckUserNameExists, _: &mut Self::Context) -> Self::Result {
@@ -125,13 +185,111 @@ impl Handler<UpdateUser> for DbActor {
     fn handle(&mut self, msg: UpdateUser, _: &mut Self::Context) -> Self::Result {
         let conn = self.0.get().expect("Unable to get a connection");
 
-        diesel::update(users_q)
-            .filter(id.eq(msg.id))
-            .set(msg)
+        conn.transaction(|| {
+            // a new password logs out every session that knew the old one
+            if msg.password_hash.is_some() {
+                revoke_all_sessions(&conn, msg.id)?;
+            }
+            diesel::update(users_q)
+                .filter(id.eq(msg.id))
+                .set(msg)
+                .get_result::<User>(&conn)
+        })
+    }
+}
+
+/// The user, locked until the transaction ends so the audit trail sees them as they
+/// were right before the change
+fn lock_user(conn: &PgConnection, user_id: Uuid) -> QueryResult<User> {
+    users_q.filter(id.eq(user_id)).for_update().get_result(conn)
+}
+
+impl Handler<DeactivateUser> for DbActor {
+    type Result = QueryResult<User>;
+
+    fn handle(&mut self, msg: DeactivateUser, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let before = lock_user(&conn, msg.id)?;
+            revoke_all_sessions(&conn, msg.id)?;
+            let user = diesel::update(users_q.filter(id.eq(msg.id)))
+                .set(active.eq(false))
+                .get_result::<User>(&conn)?;
+            if let Some(audit) = msg.audit {
+                let audit = audit
+                    .before(&AuditUserInfo::from(&before))
+                    .after(&AuditUserInfo::from(&user));
+                record_in(&conn, audit)?;
+            }
+            Ok(user)
+        })
+    }
+}
+
+impl Handler<ReactivateUser> for DbActor {
+    type Result = QueryResult<User>;
+
+    fn handle(&mut self, msg: ReactivateUser, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        diesel::update(users_q.filter(id.eq(msg.id)).filter(suspended_at.is_null()))
+            .set(active.eq(true))
             .get_result::<User>(&conn)
     }
 }
 
+impl Handler<SuspendUser> for DbActor {
+    type Result = QueryResult<User>;
+
+    fn handle(&mut self, msg: SuspendUser, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let before = lock_user(&conn, msg.id)?;
+            revoke_all_sessions(&conn, msg.id)?;
+            let user = diesel::update(users_q.filter(id.eq(msg.id)))
+                .set((
+                    active.eq(false),
+                    suspended_at.eq(Utc::now().naive_utc()),
+                    suspension_reason.eq(msg.reason),
+                ))
+                .get_result::<User>(&conn)?;
+            let audit = msg
+                .audit
+                .before(&AuditUserInfo::from(&before))
+                .after(&AuditUserInfo::from(&user));
+            record_in(&conn, audit)?;
+            Ok(user)
+        })
+    }
+}
+
+impl Handler<UnsuspendUser> for DbActor {
+    type Result = QueryResult<User>;
+
+    fn handle(&mut self, msg: UnsuspendUser, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+
+        conn.transaction(|| {
+            let before = lock_user(&conn, msg.id)?;
+            let user = diesel::update(users_q.filter(id.eq(msg.id)))
+                .set((
+                    active.eq(true),
+                    suspended_at.eq(None::<chrono::NaiveDateTime>),
+                    suspension_reason.eq(None::<String>),
+                ))
+                .get_result::<User>(&conn)?;
+            let audit = msg
+                .audit
+                .before(&AuditUserInfo::from(&before))
+                .after(&AuditUserInfo::from(&user));
+            record_in(&conn, audit)?;
+            Ok(user)
+        })
+    }
+}
+
 impl Handler<DeleteUser> for DbActor {
     type Result = QueryResult<User>;
 
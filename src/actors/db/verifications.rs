@@ -0,0 +1,104 @@
+use crate::actix::{Handler, Message};
+use crate::actors::db::DbActor;
+use crate::diesel::prelude::*;
+use crate::models::verifications::{
+    ConfirmOutcome, EmailVerification, NewEmailVerification, MAX_VERIFICATION_ATTEMPTS,
+};
+use crate::schema::email_verifications::dsl::*;
+use crate::schema::users;
+use chrono::{Duration, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+/// Minimum time between two codes for the same user
+const RESEND_INTERVAL_SECS: i64 = 60;
+
+/// Replaces any earlier code of the user with a new one.
+/// Ok(None) when the previous code was sent too recently
+#[derive(Message)]
+#[rtype(result = "QueryResult<Option<EmailVerification>>")]
+pub struct CreateEmailVerification {
+    pub user_id: Uuid,
+    pub email: String,
+    pub code_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Checks a code and marks the user's email as verified when it matches
+#[derive(Message)]
+#[rtype(result = "QueryResult<ConfirmOutcome>")]
+pub struct ConfirmEmail {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
+
+impl Handler<CreateEmailVerification> for DbActor {
+    type Result = QueryResult<Option<EmailVerification>>;
+
+    fn handle(&mut self, msg: CreateEmailVerification, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let throttled_since = Utc::now().naive_utc() - Duration::seconds(RESEND_INTERVAL_SECS);
+
+        conn.transaction(|| {
+            let recent = email_verifications
+                .filter(user_id.eq(msg.user_id))
+                .filter(created_at.gt(throttled_since))
+                .count()
+                .get_result::<i64>(&conn)?;
+            if recent > 0 {
+                return Ok(None);
+            }
+
+            diesel::delete(email_verifications.filter(user_id.eq(msg.user_id))).execute(&conn)?;
+            diesel::insert_into(email_verifications)
+                .values(NewEmailVerification {
+                    user_id: msg.user_id,
+                    email: msg.email,
+                    code_hash: msg.code_hash,
+                    expires_at: msg.expires_at,
+                })
+                .get_result::<EmailVerification>(&conn)
+                .map(Some)
+        })
+    }
+}
+
+impl Handler<ConfirmEmail> for DbActor {
+    type Result = QueryResult<ConfirmOutcome>;
+
+    fn handle(&mut self, msg: ConfirmEmail, _: &mut Self::Context) -> Self::Result {
+        let conn = self.0.get().expect("Unable to get a connection");
+        let now = Utc::now().naive_utc();
+
+        conn.transaction(|| {
+            let current_email = users::table
+                .find(msg.user_id)
+                .select(users::email)
+                .get_result::<String>(&conn)?;
+            let verification = email_verifications
+                .filter(user_id.eq(msg.user_id))
+                .filter(email.eq(&current_email))
+                .filter(expires_at.gt(now))
+                .filter(attempts.lt(MAX_VERIFICATION_ATTEMPTS))
+                .order(created_at.desc())
+                .first::<EmailVerification>(&conn)
+                .optional()?;
+
+            let verification = match verification {
+                Some(verification) => verification,
+                None => return Ok(ConfirmOutcome::Expired),
+            };
+            if verification.code_hash != msg.code_hash {
+                diesel::update(email_verifications.find(verification.id))
+                    .set(attempts.eq(attempts + 1))
+                    .execute(&conn)?;
+                return Ok(ConfirmOutcome::Invalid);
+            }
+
+            diesel::update(users::table.find(msg.user_id))
+                .set(users::email_verified.eq(true))
+                .execute(&conn)?;
+            diesel::delete(email_verifications.filter(user_id.eq(msg.user_id))).execute(&conn)?;
+            Ok(ConfirmOutcome::Verified)
+        })
+    }
+}
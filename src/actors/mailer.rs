@@ -0,0 +1,27 @@
+use crate::actix::{Actor, Handler, Message, SyncContext};
+use crate::utils::mailer::{Mail, Mailer};
+use std::sync::Arc;
+
+/// Sends mail off the request path, SMTP is blocking so this runs on a SyncArbiter
+pub struct MailActor(pub Arc<dyn Mailer>);
+impl Actor for MailActor {
+    type Context = SyncContext<Self>;
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendMail(pub Mail);
+
+impl Handler<SendMail> for MailActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendMail, _: &mut Self::Context) -> Self::Result {
+        let mail = msg.0;
+        if let Err(err) = self.0.send(&mail) {
+            error!(
+                "failed to send \"{}\" to {}: {}",
+                mail.subject, mail.to, err
+            );
+        }
+    }
+}
@@ -1 +1,4 @@
+pub mod click_writer;
 pub mod db;
+pub mod mailer;
+pub mod route_scheduler;
@@ -0,0 +1,48 @@
+use crate::actix::prelude::*;
+use crate::actors::db::route_schedules::ApplyDueSchedules;
+use crate::actors::db::DbActor;
+use chrono::Utc;
+use std::time::Duration;
+
+/// Due schedules are applied at least this often
+pub const DEFAULT_APPLY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Writes due scheduled targets to their routes. Redirects already honor due
+/// schedules on their own, this keeps `routes.target`, the history and the
+/// audit log in step with them
+pub struct RouteScheduler {
+    db: Addr<DbActor>,
+    interval: Duration,
+}
+
+impl RouteScheduler {
+    pub fn new(db: Addr<DbActor>, interval: Duration) -> Self {
+        RouteScheduler { db, interval }
+    }
+
+    fn apply(&mut self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        // runs are not awaited by the interval, a slow one makes the next skip its rows
+        ctx.spawn(
+            async move {
+                let now = Utc::now().naive_utc();
+                match db.send(ApplyDueSchedules { now }).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(count)) => info!("applied {} scheduled route targets", count),
+                    Ok(Err(err)) => error!("could not apply scheduled route targets: {}", err),
+                    Err(err) => error!("could not apply scheduled route targets: {}", err),
+                }
+            }
+            .into_actor(self),
+        );
+    }
+}
+
+impl Actor for RouteScheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.apply(ctx);
+        ctx.run_interval(self.interval, |act, ctx| act.apply(ctx));
+    }
+}
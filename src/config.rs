@@ -0,0 +1,1039 @@
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+use crate::actors::click_writer::{
+    DEFAULT_BATCH_SIZE, DEFAULT_BUFFER_CAPACITY, DEFAULT_FLUSH_INTERVAL, MAX_BUFFER_CAPACITY,
+};
+use crate::actors::db::clicks::CLICKS_PER_INSERT;
+use crate::actors::route_scheduler::DEFAULT_APPLY_INTERVAL;
+use crate::middleware::session_keys::{SessionKeys, MIN_KEY_LENGTH};
+use crate::models::routes::InactiveOwnerPolicy;
+use crate::utils::auth::DEFAULT_SESSION_TTL;
+use crate::utils::crypto::MIN_SALT_LENGTH;
+use crate::utils::geoip::GeoIp;
+use crate::utils::login_throttle::{
+    ThrottlePolicy, DEFAULT_BASE_LOCKOUT, DEFAULT_FREE_ATTEMPTS, DEFAULT_MAX_LOCKOUT,
+    DEFAULT_WINDOW,
+};
+use crate::utils::mailer::{LogMailer, Mailer, SmtpMailer, SmtpSecurity};
+use crate::utils::net::{parse_proxies, DEFAULT_TRUSTED_PROXIES};
+use crate::utils::reserved;
+use crate::utils::slug::{SlugGenerator, DEFAULT_ALPHABET, DEFAULT_LENGTH};
+use crate::utils::target::{TargetPolicy, DEFAULT_SCHEMES};
+use crate::utils::totp::SecretCipher;
+
+/// Used when ELIDE_CONFIG is not set, a missing default file is not an error
+pub const DEFAULT_CONFIG_PATH: &str = "elide.toml";
+
+/// Everything that can be tuned without a rebuild.
+///
+/// Values come from the TOML file at `ELIDE_CONFIG` (default `elide.toml`), each
+/// can then be overridden with the environment variable named next to it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub redis: RedisConfig,
+    pub session: SessionConfig,
+    pub cors: CorsConfig,
+    pub slugs: SlugsConfig,
+    pub targets: TargetsConfig,
+    pub clicks: ClicksConfig,
+    pub schedules: SchedulesConfig,
+    pub geoip: GeoIpConfig,
+    pub mail: MailConfig,
+    pub accounts: AccountsConfig,
+    pub login: LoginConfig,
+    pub two_factor: TwoFactorConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// ELIDE_SERVER_BIND, host:port to listen on
+    pub bind: String,
+    /// ELIDE_SERVER_WORKERS, defaults to the number of CPUs
+    pub workers: Option<usize>,
+    /// ELIDE_SERVER_CONSOLE_URL, where requests for '/' are sent
+    pub console_url: String,
+    /// ELIDE_SERVER_TRUSTED_PROXIES (comma separated), addresses or networks whose
+    /// X-Forwarded-For is believed, the client IP comes from the connection otherwise
+    pub trusted_proxies: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "0.0.0.0:9600".to_string(),
+            workers: None,
+            console_url: "https://console.elide.me".to_string(),
+            trusted_proxies: DEFAULT_TRUSTED_PROXIES
+                .iter()
+                .map(|proxy| proxy.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// DATABASE_URL, shared with the diesel CLI
+    pub url: String,
+    /// ELIDE_DATABASE_POOL_SIZE, max connections in the r2d2 pool
+    pub pool_size: u32,
+    /// ELIDE_DATABASE_ACTORS, number of DbActor threads
+    pub actors: usize,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            pool_size: 10,
+            actors: 1,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    /// ELIDE_REDIS_ADDRESS, host:port of the session store
+    pub address: String,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            address: "redis:6379".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// ELIDE_SESSION_KEY, signs session cookies, at least 32 bytes.
+    /// A random key is used when unset, logging everyone out on restart
+    pub key: Option<String>,
+    /// ELIDE_SESSION_OLD_KEYS (comma separated), retired keys whose cookies are still accepted
+    pub old_keys: Vec<String>,
+    /// ELIDE_SESSION_KEY_FILE, one key per line, the first is the primary key and the
+    /// rest are old keys. Takes the place of `key` and `old_keys`
+    pub key_file: Option<String>,
+    /// ELIDE_SESSION_TTL, seconds a session lives in Redis after it was last written,
+    /// older entries in the session list are dead and get pruned
+    pub ttl: u32,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            key: None,
+            old_keys: Vec::new(),
+            key_file: None,
+            ttl: DEFAULT_SESSION_TTL,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// ELIDE_CORS_ALLOWED_ORIGINS (comma separated), empty allows any origin
+    pub allowed_origins: Vec<String>,
+    /// ELIDE_CORS_MAX_AGE, seconds browsers may cache preflight responses
+    pub max_age: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            max_age: 3600 * 24 * 30, // 30 days
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlugsConfig {
+    /// ELIDE_SLUGS_ALPHABET, characters generated slugs are made of, distinct and URL-safe
+    pub alphabet: String,
+    /// ELIDE_SLUGS_LENGTH, starting length of generated slugs
+    pub length: usize,
+    /// ELIDE_SLUGS_RESERVED (comma separated), reserved on top of the defaults
+    pub reserved: Vec<String>,
+    /// ELIDE_SLUGS_ALLOWED (comma separated), defaults usable as slugs after all,
+    /// e.g. "docs". Paths the app routes itself can't be allowed
+    pub allowed: Vec<String>,
+}
+
+impl Default for SlugsConfig {
+    fn default() -> Self {
+        SlugsConfig {
+            alphabet: DEFAULT_ALPHABET.to_string(),
+            length: DEFAULT_LENGTH,
+            reserved: Vec::new(),
+            allowed: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TargetsConfig {
+    /// ELIDE_TARGETS_SCHEMES (comma separated), schemes route targets may use
+    pub schemes: Vec<String>,
+}
+
+impl Default for TargetsConfig {
+    fn default() -> Self {
+        TargetsConfig {
+            schemes: DEFAULT_SCHEMES.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClicksConfig {
+    /// ELIDE_CLICKS_IP_SALT, mixed into client IPs before hashing, at least 32 bytes.
+    /// Also keys the sticky A/B split assignment
+    pub ip_salt: String,
+    /// ELIDE_CLICKS_FLUSH_INTERVAL, seconds between batched click inserts
+    pub flush_interval: u64,
+    /// ELIDE_CLICKS_BATCH_SIZE, buffered clicks that trigger an early flush, at most 7281
+    pub batch_size: usize,
+    /// ELIDE_CLICKS_BUFFER_CAPACITY, clicks beyond this are dropped, at most 1000000
+    pub buffer_capacity: usize,
+}
+
+impl Default for ClicksConfig {
+    fn default() -> Self {
+        ClicksConfig {
+            ip_salt: String::new(),
+            flush_interval: DEFAULT_FLUSH_INTERVAL.as_secs(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+        }
+    }
+}
+
+impl ClicksConfig {
+    pub fn flush_interval(&self) -> Duration {
+        Duration::from_secs(self.flush_interval)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulesConfig {
+    /// ELIDE_SCHEDULES_APPLY_INTERVAL, seconds between runs of the job applying
+    /// scheduled target changes
+    pub apply_interval: u64,
+}
+
+impl Default for SchedulesConfig {
+    fn default() -> Self {
+        SchedulesConfig {
+            apply_interval: DEFAULT_APPLY_INTERVAL.as_secs(),
+        }
+    }
+}
+
+impl SchedulesConfig {
+    pub fn apply_interval(&self) -> Duration {
+        Duration::from_secs(self.apply_interval)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoIpConfig {
+    /// ELIDE_GEOIP_DATABASE, path of a MaxMind .mmdb file. Without it geo rules
+    /// never match and clicks have no country
+    pub database: Option<String>,
+    /// `database` as opened by Config::load
+    #[serde(skip)]
+    reader: Option<Arc<GeoIp>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    /// ELIDE_MAIL_TRANSPORT, "log" writes mails to the log (debug builds only), "smtp" sends them
+    pub transport: String,
+    /// ELIDE_MAIL_FROM, sender of every mail
+    pub from: String,
+    /// ELIDE_MAIL_SMTP_HOST
+    pub smtp_host: String,
+    /// ELIDE_MAIL_SMTP_PORT
+    pub smtp_port: u16,
+    /// ELIDE_MAIL_SMTP_TLS, one of "none", "starttls" or "tls"
+    pub smtp_tls: String,
+    /// ELIDE_MAIL_SMTP_USERNAME, no authentication when unset
+    pub smtp_username: Option<String>,
+    /// ELIDE_MAIL_SMTP_PASSWORD
+    pub smtp_password: Option<String>,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            transport: "log".to_string(),
+            from: "Elide <no-reply@elide.me>".to_string(),
+            smtp_host: "localhost".to_string(),
+            smtp_port: 587,
+            smtp_tls: "starttls".to_string(),
+            smtp_username: None,
+            smtp_password: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountsConfig {
+    /// ELIDE_ACCOUNTS_REQUIRE_VERIFIED_EMAIL, refuse to create routes until the email is verified
+    pub require_verified_email: bool,
+    /// ELIDE_ACCOUNTS_VERIFICATION_CODE_TTL, minutes an email verification code is valid
+    pub verification_code_ttl: i64,
+    /// ELIDE_ACCOUNTS_PASSWORD_RESET_TTL, minutes a password reset link is valid
+    pub password_reset_ttl: i64,
+    /// ELIDE_ACCOUNTS_INACTIVE_OWNER_ROUTES, what routes of deactivated or suspended
+    /// accounts do: "redirect", "interstitial" or "gone"
+    pub inactive_owner_routes: InactiveOwnerPolicy,
+}
+
+impl Default for AccountsConfig {
+    fn default() -> Self {
+        AccountsConfig {
+            require_verified_email: false,
+            verification_code_ttl: 15,
+            password_reset_ttl: 60,
+            inactive_owner_routes: InactiveOwnerPolicy::Gone,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginConfig {
+    /// ELIDE_LOGIN_THROTTLE, where failed logins are counted: "redis" or "memory" (per process)
+    pub throttle: String,
+    /// ELIDE_LOGIN_FREE_ATTEMPTS, failures per username or IP before lockouts start
+    pub free_attempts: u64,
+    /// ELIDE_LOGIN_BASE_LOCKOUT, seconds of the first lockout, doubled on every further failure
+    pub base_lockout: u64,
+    /// ELIDE_LOGIN_MAX_LOCKOUT, seconds of the longest lockout
+    pub max_lockout: u64,
+    /// ELIDE_LOGIN_WINDOW, seconds after the last failure when failures are forgotten
+    pub window: u64,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        LoginConfig {
+            throttle: "redis".to_string(),
+            free_attempts: DEFAULT_FREE_ATTEMPTS,
+            base_lockout: DEFAULT_BASE_LOCKOUT.as_secs(),
+            max_lockout: DEFAULT_MAX_LOCKOUT.as_secs(),
+            window: DEFAULT_WINDOW.as_secs(),
+        }
+    }
+}
+
+impl LoginConfig {
+    pub fn throttle_policy(&self) -> ThrottlePolicy {
+        ThrottlePolicy {
+            free_attempts: self.free_attempts,
+            base_lockout: Duration::from_secs(self.base_lockout),
+            max_lockout: Duration::from_secs(self.max_lockout),
+            window: Duration::from_secs(self.window),
+        }
+    }
+
+    /// Whether failed logins are counted in Redis
+    pub fn use_redis(&self) -> bool {
+        self.throttle == "redis"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TwoFactorConfig {
+    /// ELIDE_TWO_FACTOR_KEY, encrypts TOTP secrets, at least 32 bytes.
+    /// 2FA can't be enrolled or used without it, and changing it breaks existing enrollments
+    pub key: Option<String>,
+    /// ELIDE_TWO_FACTOR_ISSUER, name shown in authenticator apps
+    pub issuer: String,
+}
+
+impl Default for TwoFactorConfig {
+    fn default() -> Self {
+        TwoFactorConfig {
+            key: None,
+            issuer: "Elide".to_string(),
+        }
+    }
+}
+
+/// Every problem found while loading the configuration
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Reads the config file, applies environment overrides and validates the result
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, required) = match env::var("ELIDE_CONFIG") {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_PATH.to_string(), false),
+        };
+
+        let mut config = if required || Path::new(&path).exists() {
+            Config::from_file(&path).map_err(|problem| ConfigError(vec![problem]))?
+        } else {
+            Config::default()
+        };
+
+        let mut problems = Vec::new();
+        config.apply_env(&mut problems);
+        config.read_key_file(&mut problems);
+        config.open_geoip(&mut problems);
+        config.validate(&mut problems);
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(problems))
+        }
+    }
+
+    fn from_file(path: &str) -> Result<Config, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read config file {}: {}", path, err))?;
+        toml::from_str(&contents).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    fn apply_env(&mut self, problems: &mut Vec<String>) {
+        override_from_env(&mut self.server.bind, "ELIDE_SERVER_BIND", problems);
+        override_option_from_env(&mut self.server.workers, "ELIDE_SERVER_WORKERS", problems);
+        override_from_env(
+            &mut self.server.console_url,
+            "ELIDE_SERVER_CONSOLE_URL",
+            problems,
+        );
+        override_list_from_env(
+            &mut self.server.trusted_proxies,
+            "ELIDE_SERVER_TRUSTED_PROXIES",
+        );
+
+        override_from_env(&mut self.database.url, "DATABASE_URL", problems);
+        override_from_env(
+            &mut self.database.pool_size,
+            "ELIDE_DATABASE_POOL_SIZE",
+            problems,
+        );
+        override_from_env(&mut self.database.actors, "ELIDE_DATABASE_ACTORS", problems);
+
+        override_from_env(&mut self.redis.address, "ELIDE_REDIS_ADDRESS", problems);
+
+        override_option_from_env(&mut self.session.key, "ELIDE_SESSION_KEY", problems);
+        override_list_from_env(&mut self.session.old_keys, "ELIDE_SESSION_OLD_KEYS");
+        override_option_from_env(
+            &mut self.session.key_file,
+            "ELIDE_SESSION_KEY_FILE",
+            problems,
+        );
+        override_from_env(&mut self.session.ttl, "ELIDE_SESSION_TTL", problems);
+
+        override_list_from_env(&mut self.cors.allowed_origins, "ELIDE_CORS_ALLOWED_ORIGINS");
+        override_from_env(&mut self.cors.max_age, "ELIDE_CORS_MAX_AGE", problems);
+
+        override_from_env(&mut self.slugs.alphabet, "ELIDE_SLUGS_ALPHABET", problems);
+        override_from_env(&mut self.slugs.length, "ELIDE_SLUGS_LENGTH", problems);
+        override_list_from_env(&mut self.slugs.reserved, "ELIDE_SLUGS_RESERVED");
+        override_list_from_env(&mut self.slugs.allowed, "ELIDE_SLUGS_ALLOWED");
+
+        override_list_from_env(&mut self.targets.schemes, "ELIDE_TARGETS_SCHEMES");
+
+        override_from_env(&mut self.clicks.ip_salt, "ELIDE_CLICKS_IP_SALT", problems);
+        override_from_env(
+            &mut self.clicks.flush_interval,
+            "ELIDE_CLICKS_FLUSH_INTERVAL",
+            problems,
+        );
+        override_from_env(
+            &mut self.clicks.batch_size,
+            "ELIDE_CLICKS_BATCH_SIZE",
+            problems,
+        );
+        override_from_env(
+            &mut self.clicks.buffer_capacity,
+            "ELIDE_CLICKS_BUFFER_CAPACITY",
+            problems,
+        );
+
+        override_from_env(
+            &mut self.schedules.apply_interval,
+            "ELIDE_SCHEDULES_APPLY_INTERVAL",
+            problems,
+        );
+
+        override_option_from_env(&mut self.geoip.database, "ELIDE_GEOIP_DATABASE", problems);
+
+        override_from_env(&mut self.mail.transport, "ELIDE_MAIL_TRANSPORT", problems);
+        override_from_env(&mut self.mail.from, "ELIDE_MAIL_FROM", problems);
+        override_from_env(&mut self.mail.smtp_host, "ELIDE_MAIL_SMTP_HOST", problems);
+        override_from_env(&mut self.mail.smtp_port, "ELIDE_MAIL_SMTP_PORT", problems);
+        override_from_env(&mut self.mail.smtp_tls, "ELIDE_MAIL_SMTP_TLS", problems);
+        override_option_from_env(
+            &mut self.mail.smtp_username,
+            "ELIDE_MAIL_SMTP_USERNAME",
+            problems,
+        );
+        override_option_from_env(
+            &mut self.mail.smtp_password,
+            "ELIDE_MAIL_SMTP_PASSWORD",
+            problems,
+        );
+
+        override_from_env(
+            &mut self.accounts.require_verified_email,
+            "ELIDE_ACCOUNTS_REQUIRE_VERIFIED_EMAIL",
+            problems,
+        );
+        override_from_env(
+            &mut self.accounts.verification_code_ttl,
+            "ELIDE_ACCOUNTS_VERIFICATION_CODE_TTL",
+            problems,
+        );
+        override_from_env(
+            &mut self.accounts.password_reset_ttl,
+            "ELIDE_ACCOUNTS_PASSWORD_RESET_TTL",
+            problems,
+        );
+        override_from_env(
+            &mut self.accounts.inactive_owner_routes,
+            "ELIDE_ACCOUNTS_INACTIVE_OWNER_ROUTES",
+            problems,
+        );
+
+        override_from_env(&mut self.login.throttle, "ELIDE_LOGIN_THROTTLE", problems);
+        override_from_env(
+            &mut self.login.free_attempts,
+            "ELIDE_LOGIN_FREE_ATTEMPTS",
+            problems,
+        );
+        override_from_env(
+            &mut self.login.base_lockout,
+            "ELIDE_LOGIN_BASE_LOCKOUT",
+            problems,
+        );
+        override_from_env(
+            &mut self.login.max_lockout,
+            "ELIDE_LOGIN_MAX_LOCKOUT",
+            problems,
+        );
+        override_from_env(&mut self.login.window, "ELIDE_LOGIN_WINDOW", problems);
+
+        override_option_from_env(&mut self.two_factor.key, "ELIDE_TWO_FACTOR_KEY", problems);
+        override_from_env(
+            &mut self.two_factor.issuer,
+            "ELIDE_TWO_FACTOR_ISSUER",
+            problems,
+        );
+    }
+
+    /// Replaces session.key and session.old_keys with the contents of session.key_file
+    fn read_key_file(&mut self, problems: &mut Vec<String>) {
+        let path = match &self.session.key_file {
+            Some(path) => path,
+            None => return,
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                problems.push(format!("could not read session key file {}: {}", path, err));
+                return;
+            }
+        };
+
+        let mut keys = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string);
+        self.session.key = keys.next();
+        self.session.old_keys = keys.collect();
+        if self.session.key.is_none() {
+            problems.push(format!("session key file {} contains no keys", path));
+        }
+    }
+
+    /// Opens geoip.database once, the file can be large
+    fn open_geoip(&mut self, problems: &mut Vec<String>) {
+        if let Some(path) = &self.geoip.database {
+            match GeoIp::open(path) {
+                Ok(reader) => self.geoip.reader = Some(Arc::new(reader)),
+                Err(err) => problems.push(format!("geoip.database: {}", err)),
+            }
+        }
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        let valid_bind = match self.server.bind.rsplit_once(':') {
+            Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok(),
+            None => false,
+        };
+        if !valid_bind {
+            problems.push(format!(
+                "server.bind must look like host:port, got \"{}\"",
+                self.server.bind
+            ));
+        }
+        if self.server.workers == Some(0) {
+            problems.push("server.workers must be at least 1".to_string());
+        }
+        if Url::parse(&self.server.console_url).is_err() {
+            problems.push(format!(
+                "server.console_url must be an absolute URL, got \"{}\"",
+                self.server.console_url
+            ));
+        }
+        if let Err(err) = self.trusted_proxies() {
+            problems.push(format!("server.trusted_proxies: {}", err));
+        }
+
+        if self.database.url.is_empty() {
+            problems.push("database.url (or DATABASE_URL) must be set".to_string());
+        }
+        if self.database.pool_size == 0 {
+            problems.push("database.pool_size must be at least 1".to_string());
+        }
+        if self.database.actors == 0 {
+            problems.push("database.actors must be at least 1".to_string());
+        }
+
+        if self.redis.address.is_empty() {
+            problems.push("redis.address must be set".to_string());
+        }
+
+        let mut session_keys = self.session.key.iter().chain(&self.session.old_keys);
+        if session_keys.any(|key| key.len() < MIN_KEY_LENGTH) {
+            problems.push(format!(
+                "session keys must be at least {} bytes long",
+                MIN_KEY_LENGTH
+            ));
+        }
+        if self.session.ttl < 60 {
+            problems.push("session.ttl must be at least 60 seconds".to_string());
+        }
+        if self.session.key.is_none() && !self.session.old_keys.is_empty() {
+            problems.push("session.old_keys requires session.key".to_string());
+        }
+        if self.session.key.is_none() {
+            warn!("session.key is not set, sessions will not survive a restart");
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if Url::parse(origin).is_err() {
+                problems.push(format!(
+                    "cors.allowed_origins entries must be URLs like https://example.com, got \"{}\"",
+                    origin
+                ));
+            }
+        }
+
+        if let Err(err) = self.slug_generator() {
+            problems.push(format!("slugs: {}", err));
+        }
+        for slug in &self.slugs.allowed {
+            let slug = slug.trim().to_lowercase();
+            if reserved::ROUTED.contains(&slug.as_str()) {
+                problems.push(format!(
+                    "slugs.allowed can't include \"{}\", the app routes it itself",
+                    slug
+                ));
+            } else if !reserved::DEFAULT_RESERVED.contains(&slug.as_str()) {
+                problems.push(format!(
+                    "slugs.allowed only takes built-in reserved slugs, \"{}\" isn't one",
+                    slug
+                ));
+            }
+        }
+        if let Err(err) = self.target_policy() {
+            problems.push(format!("targets: {}", err));
+        }
+
+        if self.clicks.flush_interval == 0 {
+            problems.push("clicks.flush_interval must be at least 1 second".to_string());
+        }
+        if self.clicks.batch_size == 0 || self.clicks.batch_size > CLICKS_PER_INSERT {
+            problems.push(format!(
+                "clicks.batch_size must be between 1 and {}",
+                CLICKS_PER_INSERT
+            ));
+        }
+        if self.clicks.buffer_capacity < self.clicks.batch_size
+            || self.clicks.buffer_capacity > MAX_BUFFER_CAPACITY
+        {
+            problems.push(format!(
+                "clicks.buffer_capacity must be between clicks.batch_size and {}",
+                MAX_BUFFER_CAPACITY
+            ));
+        }
+        if self.clicks.ip_salt.len() < MIN_SALT_LENGTH {
+            problems.push(format!(
+                "clicks.ip_salt must be at least {} bytes long, hashed client IPs are easy to reverse otherwise",
+                MIN_SALT_LENGTH
+            ));
+        }
+
+        if self.schedules.apply_interval == 0 {
+            problems.push("schedules.apply_interval must be at least 1 second".to_string());
+        }
+
+        if let Err(err) = self.mailer() {
+            problems.push(format!("mail: {}", err));
+        }
+        if self.mail.smtp_username.is_some() != self.mail.smtp_password.is_some() {
+            problems.push("mail.smtp_username and mail.smtp_password go together".to_string());
+        }
+        if self.accounts.verification_code_ttl < 1 {
+            problems.push("accounts.verification_code_ttl must be at least 1 minute".to_string());
+        }
+        if self.accounts.password_reset_ttl < 1 {
+            problems.push("accounts.password_reset_ttl must be at least 1 minute".to_string());
+        }
+
+        if !["redis", "memory"].contains(&self.login.throttle.as_str()) {
+            problems.push(format!(
+                "login.throttle must be \"redis\" or \"memory\", got \"{}\"",
+                self.login.throttle
+            ));
+        }
+        if self.login.base_lockout == 0 {
+            problems.push("login.base_lockout must be at least 1 second".to_string());
+        }
+        if self.login.max_lockout < self.login.base_lockout {
+            problems.push("login.max_lockout must not be below login.base_lockout".to_string());
+        }
+        if self.login.window < self.login.max_lockout {
+            problems.push("login.window must not be below login.max_lockout".to_string());
+        }
+
+        if self
+            .two_factor
+            .key
+            .as_ref()
+            .is_some_and(|key| key.len() < MIN_KEY_LENGTH)
+        {
+            problems.push(format!(
+                "two_factor.key must be at least {} bytes long",
+                MIN_KEY_LENGTH
+            ));
+        }
+        if self.two_factor.issuer.is_empty() || self.two_factor.issuer.contains(':') {
+            problems.push("two_factor.issuer must be set and must not contain ':'".to_string());
+        }
+    }
+
+    /// None when no key is configured
+    pub fn session_keys(&self) -> Option<SessionKeys> {
+        self.session.key.as_ref().map(|primary| SessionKeys {
+            primary: primary.as_bytes().to_vec(),
+            old: self
+                .session
+                .old_keys
+                .iter()
+                .map(|key| key.as_bytes().to_vec())
+                .collect(),
+        })
+    }
+
+    pub fn trusted_proxies(&self) -> Result<Vec<IpNet>, String> {
+        parse_proxies(&self.server.trusted_proxies)
+    }
+
+    pub fn slug_generator(&self) -> Result<SlugGenerator, String> {
+        SlugGenerator::new(&self.slugs.alphabet, self.slugs.length)
+    }
+
+    pub fn target_policy(&self) -> Result<TargetPolicy, String> {
+        TargetPolicy::new(&self.targets.schemes)
+    }
+
+    /// None when no GeoIP database is configured
+    pub fn geoip(&self) -> Option<Arc<GeoIp>> {
+        self.geoip.reader.clone()
+    }
+
+    /// None when 2FA is not configured
+    pub fn totp_cipher(&self) -> Option<SecretCipher> {
+        self.two_factor.key.as_deref().map(SecretCipher::new)
+    }
+
+    pub fn mailer(&self) -> Result<Arc<dyn Mailer>, String> {
+        match self.mail.transport.as_str() {
+            "log" if cfg!(debug_assertions) => Ok(Arc::new(LogMailer)),
+            // codes and reset links in the log would let anyone with log access take over accounts
+            "log" => {
+                Err("transport \"log\" is only allowed in debug builds, use \"smtp\"".to_string())
+            }
+            "smtp" => {
+                let security = match self.mail.smtp_tls.as_str() {
+                    "none" => SmtpSecurity::None,
+                    "starttls" => SmtpSecurity::StartTls,
+                    "tls" => SmtpSecurity::Tls,
+                    other => {
+                        return Err(format!(
+                            "smtp_tls must be \"none\", \"starttls\" or \"tls\", got \"{}\"",
+                            other
+                        ))
+                    }
+                };
+                let credentials = self
+                    .mail
+                    .smtp_username
+                    .clone()
+                    .zip(self.mail.smtp_password.clone());
+                let mailer = SmtpMailer::new(
+                    &self.mail.smtp_host,
+                    self.mail.smtp_port,
+                    security,
+                    credentials,
+                    &self.mail.from,
+                )?;
+                Ok(Arc::new(mailer))
+            }
+            other => Err(format!(
+                "transport must be \"log\" or \"smtp\", got \"{}\"",
+                other
+            )),
+        }
+    }
+}
+
+fn override_from_env<T>(value: &mut T, name: &str, problems: &mut Vec<String>)
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if let Ok(raw) = env::var(name) {
+        match raw.parse() {
+            Ok(parsed) => *value = parsed,
+            Err(err) => problems.push(format!("{}: {}", name, err)),
+        }
+    }
+}
+
+fn override_option_from_env<T>(value: &mut Option<T>, name: &str, problems: &mut Vec<String>)
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if let Ok(raw) = env::var(name) {
+        match raw.parse() {
+            Ok(parsed) => *value = Some(parsed),
+            Err(err) => problems.push(format!("{}: {}", name, err)),
+        }
+    }
+}
+
+fn override_list_from_env(value: &mut Vec<String>, name: &str) {
+    if let Ok(raw) = env::var(name) {
+        *value = raw
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALT: &str = "0123456789abcdef0123456789abcdef";
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.database.url = "postgres://elide@localhost/elide".to_string();
+        config.clicks.ip_salt = SALT.to_string();
+        config
+    }
+
+    fn problems(config: &Config) -> Vec<String> {
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        problems
+    }
+
+    /// Whether one of the problems mentions `needle`
+    fn reported(problems: &[String], needle: &str) -> bool {
+        problems.iter().any(|problem| problem.contains(needle))
+    }
+
+    #[test]
+    fn toml_overrides_only_what_it_sets() {
+        let config: Config = toml::from_str(
+            r#"
+            [server]
+            bind = "0.0.0.0:9000"
+            workers = 2
+
+            [slugs]
+            reserved = ["pricing"]
+            allowed = ["docs"]
+
+            [clicks]
+            batch_size = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.bind, "0.0.0.0:9000");
+        assert_eq!(config.server.workers, Some(2));
+        assert_eq!(config.slugs.reserved, vec!["pricing"]);
+        assert_eq!(config.slugs.allowed, vec!["docs"]);
+        assert_eq!(config.clicks.batch_size, 100);
+        // untouched sections and keys keep their defaults
+        assert_eq!(config.slugs.length, DEFAULT_LENGTH);
+        assert_eq!(config.clicks.buffer_capacity, DEFAULT_BUFFER_CAPACITY);
+        assert_eq!(config.session.ttl, DEFAULT_SESSION_TTL);
+    }
+
+    #[test]
+    fn toml_mistakes_are_errors() {
+        // a typo must not silently fall back to the default
+        assert!(toml::from_str::<Config>("[server]\nbnd = \"0.0.0.0:80\"").is_err());
+        assert!(toml::from_str::<Config>("[nonsense]\n").is_err());
+        assert!(toml::from_str::<Config>("[clicks]\nbatch_size = \"many\"").is_err());
+    }
+
+    #[test]
+    fn env_overrides() {
+        // the only test touching these variables, tests run in parallel
+        env::set_var("ELIDE_SERVER_WORKERS", "4");
+        env::set_var(
+            "ELIDE_CORS_ALLOWED_ORIGINS",
+            "https://a.example, https://b.example,",
+        );
+        env::set_var("ELIDE_SLUGS_LENGTH", "six");
+        env::set_var("ELIDE_CLICKS_BATCH_SIZE", "-1");
+
+        let mut config = valid();
+        let mut problems = Vec::new();
+        config.apply_env(&mut problems);
+
+        for name in &[
+            "ELIDE_SERVER_WORKERS",
+            "ELIDE_CORS_ALLOWED_ORIGINS",
+            "ELIDE_SLUGS_LENGTH",
+            "ELIDE_CLICKS_BATCH_SIZE",
+        ] {
+            env::remove_var(name);
+        }
+
+        assert_eq!(config.server.workers, Some(4));
+        assert_eq!(
+            config.cors.allowed_origins,
+            vec!["https://a.example", "https://b.example"]
+        );
+        // malformed values are reported by variable name and leave the value alone
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(reported(&problems, "ELIDE_SLUGS_LENGTH: invalid digit"));
+        assert!(reported(
+            &problems,
+            "ELIDE_CLICKS_BATCH_SIZE: invalid digit"
+        ));
+        assert_eq!(config.slugs.length, DEFAULT_LENGTH);
+        assert_eq!(config.clicks.batch_size, DEFAULT_BATCH_SIZE);
+    }
+
+    #[test]
+    fn defaults_need_database_and_salt() {
+        assert!(problems(&valid()).is_empty());
+        let problems = problems(&Config::default());
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(reported(&problems, "database.url"));
+        assert!(reported(&problems, "clicks.ip_salt"));
+    }
+
+    #[test]
+    fn bad_values_are_reported() {
+        let mut config = valid();
+        config.server.bind = "localhost".to_string();
+        config.session.ttl = 10;
+        config.clicks.ip_salt = "short".to_string();
+        config.clicks.batch_size = CLICKS_PER_INSERT + 1;
+        config.clicks.buffer_capacity = MAX_BUFFER_CAPACITY + 1;
+        config.cors.allowed_origins = vec!["not a url".to_string()];
+        config.server.trusted_proxies = vec!["localhost".to_string()];
+        config.slugs.alphabet = "aab".to_string();
+        config.login.max_lockout = 0;
+
+        let problems = problems(&config);
+        for key in &[
+            "server.bind",
+            "session.ttl",
+            "clicks.ip_salt",
+            "clicks.batch_size",
+            "clicks.buffer_capacity",
+            "cors.allowed_origins",
+            "server.trusted_proxies",
+            "slugs:",
+            "login.max_lockout",
+        ] {
+            assert!(reported(&problems, key), "{} in {:?}", key, problems);
+        }
+    }
+
+    #[test]
+    fn allowed_slugs_must_be_builtin_and_unrouted() {
+        let mut config = valid();
+        config.slugs.allowed = vec!["docs".to_string(), "Help".to_string()];
+        assert!(problems(&config).is_empty());
+
+        config.slugs.allowed = vec!["API".to_string(), "my-link".to_string()];
+        let problems = problems(&config);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(reported(&problems, "\"api\", the app routes it"));
+        assert!(reported(&problems, "\"my-link\" isn't one"));
+    }
+
+    #[test]
+    fn unreadable_geoip_database_is_a_problem() {
+        let mut config = valid();
+        config.geoip.database = Some("/nonexistent/GeoLite2-Country.mmdb".to_string());
+        let mut problems = Vec::new();
+        config.open_geoip(&mut problems);
+        assert_eq!(problems.len(), 1);
+        assert!(reported(&problems, "geoip.database"));
+        assert!(config.geoip().is_none());
+
+        let mut config = valid();
+        config.open_geoip(&mut problems);
+        assert_eq!(problems.len(), 1);
+    }
+}
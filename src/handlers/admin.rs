@@ -0,0 +1,313 @@
+use crate::actors::db::admin::{
+    GetSystemStats, ReassignRoute, SearchRoutes, SearchUsers, SetRouteActive,
+};
+use crate::actors::db::audit::GetAuditEvents;
+use crate::actors::db::users::{DeactivateUser, SuspendUser, UnsuspendUser};
+use crate::models::audit::{
+    NewAuditEvent, ACTION_ADMIN_ROUTE_DISABLE, ACTION_ADMIN_ROUTE_ENABLE,
+    ACTION_ADMIN_ROUTE_REASSIGN, ACTION_ADMIN_USER_DEACTIVATE, ACTION_ADMIN_USER_SUSPEND,
+    ACTION_ADMIN_USER_UNSUSPEND, TARGET_ROUTE, TARGET_USER,
+};
+use crate::models::extras::Pagination;
+use crate::models::routes::AdminRouteInfo;
+use crate::models::users::AdminUserInfo;
+use crate::models::AppState;
+use crate::utils::auth::AdminUser;
+use crate::utils::net::client_ip;
+use serde::Deserialize;
+use serde_json::json;
+use validator::Validate;
+
+use actix_web::{
+    get, post,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder,
+};
+use uuid::Uuid;
+
+// Every change made here is written to the audit trail, with the target before and after
+
+#[derive(Debug, Deserialize, Validate)]
+struct SuspendData {
+    /// shown to the user when they try to log in
+    #[validate(length(min = 1, max = 500))]
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReassignData {
+    /// the new owner
+    user_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    /// matched case-insensitively anywhere in the searched fields
+    q: Option<String>,
+    /// only routes of this user
+    owner: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditQuery {
+    /// only events done by this user
+    actor: Option<Uuid>,
+    /// only events in this user's history
+    user: Option<Uuid>,
+    /// only events about this user, route or token
+    target: Option<Uuid>,
+}
+
+#[get("/users")]
+async fn search_users(
+    query: Query<SearchQuery>,
+    page: Query<Pagination>,
+    _admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    match db
+        .send(SearchUsers {
+            query: query.into_inner().q,
+            limit: page.limit(),
+            offset: page.offset(),
+        })
+        .await
+    {
+        Ok(Ok(users)) => HttpResponse::Ok().json(
+            users
+                .into_iter()
+                .map(AdminUserInfo::from)
+                .collect::<Vec<_>>(),
+        ),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/users/{id}/suspend")]
+async fn suspend_user(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    data: Json<SuspendData>,
+    admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if data.validate().is_err() {
+        return HttpResponse::BadRequest().json("Give a reason of at most 500 characters");
+    }
+    if id == admin.id {
+        return HttpResponse::BadRequest().json("Admins can't suspend themselves");
+    }
+    let reason = data.into_inner().reason;
+    let audit = NewAuditEvent::new(ACTION_ADMIN_USER_SUSPEND, TARGET_USER, id)
+        .by_for(admin.id, Some(id))
+        .details(json!({ "reason": reason }))
+        .ip(client_ip(&req));
+
+    match db.send(SuspendUser { id, reason, audit }).await {
+        Ok(Ok(user)) => HttpResponse::Ok().json(AdminUserInfo::from(user)),
+        Ok(Err(diesel::result::Error::NotFound)) => HttpResponse::NotFound().json("User not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/users/{id}/unsuspend")]
+async fn unsuspend_user(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    let audit = NewAuditEvent::new(ACTION_ADMIN_USER_UNSUSPEND, TARGET_USER, id)
+        .by_for(admin.id, Some(id))
+        .ip(client_ip(&req));
+
+    match db.send(UnsuspendUser { id, audit }).await {
+        Ok(Ok(user)) => HttpResponse::Ok().json(AdminUserInfo::from(user)),
+        Ok(Err(diesel::result::Error::NotFound)) => HttpResponse::NotFound().json("User not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// Deactivates like the owner would, they can reactivate it themselves
+#[post("/users/{id}/deactivate")]
+async fn deactivate_account(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if id == admin.id {
+        return HttpResponse::BadRequest().json("Admins can't deactivate themselves here");
+    }
+
+    let audit = NewAuditEvent::new(ACTION_ADMIN_USER_DEACTIVATE, TARGET_USER, id)
+        .by_for(admin.id, Some(id))
+        .ip(client_ip(&req));
+
+    match db
+        .send(DeactivateUser {
+            id,
+            audit: Some(audit),
+        })
+        .await
+    {
+        Ok(Ok(user)) => HttpResponse::Ok().json(AdminUserInfo::from(user)),
+        Ok(Err(diesel::result::Error::NotFound)) => HttpResponse::NotFound().json("User not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[get("/routes")]
+async fn search_routes(
+    query: Query<SearchQuery>,
+    page: Query<Pagination>,
+    _admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let query = query.into_inner();
+
+    match db
+        .send(SearchRoutes {
+            query: query.q,
+            creator_id: query.owner,
+            limit: page.limit(),
+            offset: page.offset(),
+        })
+        .await
+    {
+        Ok(Ok(routes)) => HttpResponse::Ok().json(
+            routes
+                .into_iter()
+                .map(AdminRouteInfo::from)
+                .collect::<Vec<_>>(),
+        ),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/routes/{id}/reassign")]
+async fn reassign_route(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    data: Json<ReassignData>,
+    admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let new_owner = data.user_id;
+
+    // the new owner sees it, the old one finds it in the admin trail
+    let audit = NewAuditEvent::new(ACTION_ADMIN_ROUTE_REASSIGN, TARGET_ROUTE, id)
+        .by_for(admin.id, Some(new_owner))
+        .ip(client_ip(&req));
+
+    match db
+        .send(ReassignRoute {
+            id,
+            creator_id: new_owner,
+            changed_by: admin.id,
+            audit,
+        })
+        .await
+    {
+        Ok(Ok(route)) => HttpResponse::Ok().json(AdminRouteInfo::from(route)),
+        Ok(Err(diesel::result::Error::NotFound)) => {
+            HttpResponse::NotFound().json("Route or user not found")
+        }
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+async fn set_route_active(
+    req: HttpRequest,
+    id: Uuid,
+    active: bool,
+    admin: AdminUser,
+    state: Data<AppState>,
+) -> HttpResponse {
+    let db = state.as_ref().db.clone();
+
+    let action = if active {
+        ACTION_ADMIN_ROUTE_ENABLE
+    } else {
+        ACTION_ADMIN_ROUTE_DISABLE
+    };
+    let audit = NewAuditEvent::new(action, TARGET_ROUTE, id).ip(client_ip(&req));
+
+    match db
+        .send(SetRouteActive {
+            id,
+            active,
+            changed_by: admin.id,
+            audit,
+        })
+        .await
+    {
+        Ok(Ok(route)) => HttpResponse::Ok().json(AdminRouteInfo::from(route)),
+        Ok(Err(diesel::result::Error::NotFound)) => {
+            HttpResponse::NotFound().json("Route not found")
+        }
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/routes/{id}/disable")]
+async fn disable_route(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    set_route_active(req, id, false, admin, state).await
+}
+
+#[post("/routes/{id}/enable")]
+async fn enable_route(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    set_route_active(req, id, true, admin, state).await
+}
+
+#[get("/stats")]
+async fn system_stats(_admin: AdminUser, state: Data<AppState>) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    match db.send(GetSystemStats).await {
+        Ok(Ok(stats)) => HttpResponse::Ok().json(stats),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[get("/audit")]
+async fn audit_trail(
+    query: Query<AuditQuery>,
+    page: Query<Pagination>,
+    _admin: AdminUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    match db
+        .send(GetAuditEvents {
+            actor_id: query.actor,
+            user_id: query.user,
+            target_id: query.target,
+            limit: page.limit(),
+            offset: page.offset(),
+        })
+        .await
+    {
+        Ok(Ok(events)) => HttpResponse::Ok().json(events),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
@@ -38,9 +38,9 @@ async fn username_availability(username: Json<Username>, state: Data<AppState>)
             username: username.username,
         })
         .await;
-    return HttpResponse::Ok().json(Availability {
+    HttpResponse::Ok().json(Availability {
         available: result.unwrap(),
-    });
+    })
 }
 
 #[get("/email")]
@@ -48,9 +48,9 @@ async fn email_availability(email: Json<Email>, state: Data<AppState>) -> impl R
     let db = state.as_ref().db.clone();
     let email = email.into_inner();
     let result = db.send(EmailAvailable { email: email.email }).await;
-    return HttpResponse::Ok().json(Availability {
+    HttpResponse::Ok().json(Availability {
         available: result.unwrap(),
-    });
+    })
 }
 
 #[get("/slug")]
@@ -58,7 +58,7 @@ async fn slug_availability(slug: Json<Slug>, state: Data<AppState>) -> impl Resp
     let db = state.as_ref().db.clone();
     let slug = slug.into_inner();
     let result = db.send(RouteSlugAvailable { slug: slug.slug }).await;
-    return HttpResponse::Ok().json(Availability {
+    HttpResponse::Ok().json(Availability {
         available: result.unwrap(),
-    });
+    })
 }
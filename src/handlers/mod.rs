@@ -1,4 +1,10 @@
+pub mod admin;
 pub mod redirects;
+pub mod route_targets;
 pub mod routes;
+pub mod schedules;
+pub mod sessions;
+pub mod tokens;
+pub mod two_factor;
 pub mod users;
 pub mod availability;
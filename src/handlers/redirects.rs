@@ -1,34 +1,207 @@
+use crate::actors::click_writer::TrackClick;
 use crate::actors::db::routes::ReadRouteBySlug;
+use crate::models::clicks::NewClick;
+use crate::models::routes::{InactiveOwnerPolicy, RouteWindow};
 use crate::models::AppState;
+use crate::utils::accept_language;
+use crate::utils::crypto::hash_ip;
+use crate::utils::net::client_ip;
+use crate::utils::rules::{self, RequestInfo};
+use crate::utils::split;
+use crate::utils::user_agent::{self, UserAgentInfo};
 use actix_web::{
+    cookie::{Cookie, SameSite},
+    dev::HttpResponseBuilder,
     get,
+    http::header,
     web::{Data, Path},
-    HttpResponse, Responder,
+    HttpRequest, HttpResponse, Responder,
 };
+use chrono::Utc;
+use uuid::Uuid;
 
 #[get("/{slug}")]
-async fn redirect_by_slug(Path(p_slug): Path<String>, state: Data<AppState>) -> impl Responder {
+async fn redirect_by_slug(
+    req: HttpRequest,
+    Path(p_slug): Path<String>,
+    state: Data<AppState>,
+) -> impl Responder {
     let db = state.as_ref().db.clone();
-    match db.send(ReadRouteBySlug { slug: p_slug }).await {
-        Ok(Ok(route)) => {
-            if route.active {
-                HttpResponse::TemporaryRedirect()
-                    .header("Location", route.target)
-                    .finish()
-            } else {
-                HttpResponse::Found().json("Route inactive")
-            }
+    let now = Utc::now().naive_utc();
+    let lookup = match db.send(ReadRouteBySlug { slug: p_slug, now }).await {
+        Ok(Ok(lookup)) => lookup,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("Route not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    let route = &lookup.route;
+    if !route.active {
+        return HttpResponse::Found().json("Route inactive");
+    }
+    match route.window(now) {
+        RouteWindow::Live => {}
+        // tell clients when to come back
+        RouteWindow::Pending(from) => {
+            return HttpResponse::ServiceUnavailable()
+                .header("Retry-After", (from - now).num_seconds().max(1).to_string())
+                .json("Route not live yet")
         }
-        Ok(Err(_)) => HttpResponse::NotFound().json("Route not found"),
-        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+        RouteWindow::Expired => return HttpResponse::Gone().json("Route expired"),
+    }
+    let owner_inactive = lookup.owner_active == Some(false);
+    if owner_inactive && state.inactive_owner_routes == InactiveOwnerPolicy::Gone {
+        return HttpResponse::Gone().json("Route unavailable");
     }
+
+    // the first matching rule wins, then an A/B split, then the route's own target
+    let ip = client_ip(&req);
+    let user_agent = header_value(&req, header::USER_AGENT);
+    let agent = user_agent
+        .as_deref()
+        .map(user_agent::parse)
+        .unwrap_or_default();
+    let location = state
+        .geoip
+        .as_ref()
+        .zip(ip.as_deref())
+        .map(|(geoip, ip)| geoip.lookup(ip))
+        .unwrap_or_default();
+    let languages = header_value(&req, header::ACCEPT_LANGUAGE)
+        .map(|header| accept_language::parse(&header))
+        .unwrap_or_default();
+    let rule_target = rules::evaluate(
+        &route.rules,
+        &RequestInfo {
+            agent: &agent,
+            location: &location,
+            languages: &languages,
+        },
+    );
+    let mut visitor_cookie = None;
+    let variant = if rule_target.is_some() || lookup.variants.is_empty() {
+        None
+    } else {
+        let (visitor, is_new) = split::visitor_id(&req, ip.as_deref(), &state.ip_salt);
+        let variant = split::pick(&lookup.variants, &visitor, route.id);
+        if is_new {
+            visitor_cookie = Some(visitor);
+        }
+        variant
+    };
+    let target = rule_target
+        .or_else(|| variant.map(|variant| variant.target.as_str()))
+        .unwrap_or_else(|| lookup.target());
+
+    let per_visitor = !route.rules.is_empty() || !lookup.variants.is_empty();
+    // a warning page is not a redirect, so it is not counted as a click
+    if owner_inactive && state.inactive_owner_routes == InactiveOwnerPolicy::Interstitial {
+        let mut response = HttpResponse::Ok();
+        if per_visitor {
+            vary_by_visitor(&mut response);
+        }
+        return response
+            .content_type("text/html; charset=utf-8")
+            .body(interstitial(target));
+    }
+
+    // analytics must never hold up the redirect
+    state.clicks.do_send(TrackClick {
+        click: new_click(
+            &req,
+            route.id,
+            ip.map(|ip| hash_ip(&ip, &state.ip_salt)),
+            user_agent,
+            agent,
+            location.country,
+            variant.map(|variant| variant.id),
+        ),
+    });
+    let mut response = HttpResponse::TemporaryRedirect();
+    response.header("Location", target);
+    if per_visitor {
+        vary_by_visitor(&mut response);
+    }
+    if let Some(visitor) = visitor_cookie {
+        response.cookie(
+            Cookie::build(split::VISITOR_COOKIE, visitor)
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Lax)
+                .max_age(time::Duration::days(365))
+                .finish(),
+        );
+    }
+    response.finish()
+}
+
+/// Where a route with rules or variants sends a visitor depends on who they are, so
+/// shared caches must not keep the answer
+fn vary_by_visitor(response: &mut HttpResponseBuilder) {
+    response
+        .header(header::CACHE_CONTROL, "private, no-store")
+        .header(header::VARY, "User-Agent, Accept-Language");
+}
+
+fn header_value(req: &HttpRequest, name: header::HeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// Collects what we track about a single hit on a route
+fn new_click(
+    req: &HttpRequest,
+    route_id: Uuid,
+    ip_hash: Option<String>,
+    user_agent: Option<String>,
+    agent: UserAgentInfo,
+    country: Option<String>,
+    variant_id: Option<Uuid>,
+) -> NewClick {
+    NewClick {
+        route_id,
+        clicked_at: Utc::now().naive_utc(),
+        referrer: header_value(req, header::REFERER),
+        user_agent,
+        ip_hash,
+        browser: agent.browser,
+        os: agent.os,
+        country,
+        variant_id,
+    }
+}
+
+/// Warning page shown instead of redirecting when the route's owner is inactive
+fn interstitial(target: &str) -> String {
+    let target = escape_html(target);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><meta name=\"robots\" content=\"noindex\">\
+         <title>Link unavailable</title></head>\n<body>\n\
+         <h1>This link's owner account is not active</h1>\n\
+         <p>It points to <code>{target}</code>. Only continue if you trust that address.</p>\n\
+         <p><a href=\"{target}\" rel=\"noopener noreferrer nofollow\">Continue</a></p>\n\
+         </body>\n</html>\n",
+        target = target
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
 }
 
 /// When user requests 'elide.me' they are looking for info i.e. frontend
 #[get("/")]
-async fn redirect_to_console() -> impl Responder {
-    // get link from config
+async fn redirect_to_console(state: Data<AppState>) -> impl Responder {
     HttpResponse::PermanentRedirect()
-        .header("Location", "https://console.elide.me")
+        .header("Location", state.console_url.as_str())
         .finish()
 }
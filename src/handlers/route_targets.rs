@@ -0,0 +1,175 @@
+use crate::actors::db::route_targets::{
+    CreateRouteTarget, DeleteRouteTarget, GetRouteTargets, UpdateRouteTarget,
+};
+use crate::handlers::routes::validation_error;
+use crate::models::api_tokens::{SCOPE_ROUTES_READ, SCOPE_ROUTES_WRITE};
+use crate::models::audit::{
+    NewAuditEvent, ACTION_ROUTE_VARIANT_CREATE, ACTION_ROUTE_VARIANT_DELETE,
+    ACTION_ROUTE_VARIANT_UPDATE, TARGET_ROUTE,
+};
+use crate::models::route_targets::{
+    NewRouteTarget, RouteTargetData, UpdateRouteTargetData, MAX_VARIANTS,
+};
+use crate::models::AppState;
+use crate::utils::audit::record;
+use crate::utils::auth::AuthUser;
+use serde_json::json;
+use validator::Validate;
+
+use actix_web::{
+    delete, get, post, put,
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse, Responder,
+};
+use uuid::Uuid;
+
+// A/B split variants of a route, mounted under /api/routes/
+
+#[get("/{id}/targets")]
+async fn get_route_targets(
+    Path(id): Path<Uuid>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if !user.has_scope(SCOPE_ROUTES_READ) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_READ);
+    }
+
+    match db
+        .send(GetRouteTargets {
+            route_id: id,
+            creator_id: user.id,
+        })
+        .await
+    {
+        Ok(Ok(variants)) => HttpResponse::Ok().json(variants),
+        Ok(Err(_)) => HttpResponse::NotFound().json("Route not found, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/{id}/targets")]
+async fn create_route_target(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    data: Json<RouteTargetData>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let data = data.into_inner();
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
+    }
+
+    let mut errors = data.validate().err().unwrap_or_default();
+    let target = match state.targets.normalize(&data.target) {
+        Ok(target) if errors.is_empty() => target,
+        Ok(_) => return validation_error(errors),
+        Err(error) => {
+            errors.add("target", error);
+            return validation_error(errors);
+        }
+    };
+
+    match db
+        .send(CreateRouteTarget {
+            creator_id: user.id,
+            variant: NewRouteTarget {
+                route_id: id,
+                target,
+                weight: data.weight,
+                label: data.label,
+            },
+        })
+        .await
+    {
+        Ok(Ok(None)) => HttpResponse::BadRequest()
+            .json(format!("A route can have at most {} targets", MAX_VARIANTS)),
+        Ok(Ok(Some(variant))) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_VARIANT_CREATE, TARGET_ROUTE, id)
+                .by(user.id)
+                .details(json!({ "variant_id": variant.id }))
+                .after(&variant);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(variant)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("Route not found, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// Changes weight and label, a weight of 0 pauses the variant
+#[put("/{id}/targets/{variant_id}")]
+async fn update_route_target(
+    req: HttpRequest,
+    Path((id, variant_id)): Path<(Uuid, Uuid)>,
+    data: Json<UpdateRouteTargetData>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let data = data.into_inner();
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
+    }
+    if let Err(errors) = data.validate() {
+        return validation_error(errors);
+    }
+
+    match db
+        .send(UpdateRouteTarget {
+            id: variant_id,
+            route_id: id,
+            creator_id: user.id,
+            weight: data.weight,
+            label: data.label,
+        })
+        .await
+    {
+        Ok(Ok(variant)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_VARIANT_UPDATE, TARGET_ROUTE, id)
+                .by(user.id)
+                .details(json!({ "variant_id": variant.id }))
+                .after(&variant);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(variant)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("Target not found, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[delete("/{id}/targets/{variant_id}")]
+async fn delete_route_target(
+    req: HttpRequest,
+    Path((id, variant_id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
+    }
+
+    match db
+        .send(DeleteRouteTarget {
+            id: variant_id,
+            route_id: id,
+            creator_id: user.id,
+        })
+        .await
+    {
+        Ok(Ok(variant)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_VARIANT_DELETE, TARGET_ROUTE, id)
+                .by(user.id)
+                .details(json!({ "variant_id": variant.id }))
+                .before(&variant);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(variant)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("Target not found, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
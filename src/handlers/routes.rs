@@ -1,43 +1,176 @@
-use crate::actors::db::routes::{CreateRoute, DeleteRoute, GetMyRoutes, UpdateRoute};
-use crate::models::routes::RouteData;
+use crate::actors::db::clicks::GetRouteStats;
+use crate::actors::db::route_versions::{GetRouteHistory, GetRouteVersion, RollbackRoute};
+use crate::actors::db::routes::{CreateRoute, DeleteRoute, GetMyRoutes, GetRoute, UpdateRoute};
+use crate::actors::db::users::GetUser;
+use crate::models::api_tokens::{SCOPE_ROUTES_READ, SCOPE_ROUTES_WRITE, SCOPE_STATS_READ};
+use crate::models::audit::{
+    NewAuditEvent, ACTION_ROUTE_CREATE, ACTION_ROUTE_DELETE, ACTION_ROUTE_ROLLBACK,
+    ACTION_ROUTE_UPDATE, TARGET_ROUTE,
+};
+use crate::models::clicks::StatsBucket;
+use crate::models::extras::AppError;
+use crate::models::route_rules::RouteRules;
+use crate::models::routes::{validate_window, Route, RouteData};
 use crate::models::AppState;
+use crate::utils::audit::record;
+use crate::utils::auth::AuthUser;
+use crate::utils::reserved::is_reserved;
+use crate::utils::slug::{COLLISIONS_PER_GROWTH, MAX_ATTEMPTS};
+use crate::utils::target::TargetPolicy;
+use actix::MailboxError;
 use actix_session::Session;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::result::DatabaseErrorKind::UniqueViolation;
 use diesel::result::Error::DatabaseError;
+use diesel::QueryResult;
 use serde::Deserialize;
+use serde_json::json;
+use std::sync::atomic::Ordering;
+use validator::{Validate, ValidationError, ValidationErrors};
 
 use actix_web::{
     delete, get, post, put,
-    web::{Data, Json, Path},
-    HttpResponse, Responder,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder,
 };
 use uuid::Uuid;
 
+/// Turns validation errors into a 400 carrying the first error message and all field errors
+pub(crate) fn validation_error(errors: ValidationErrors) -> HttpResponse {
+    let error = errors
+        .field_errors()
+        .values()
+        .flat_map(|errors| errors.iter())
+        .find_map(|error| error.message.clone())
+        .map(|message| message.to_string())
+        .unwrap_or_else(|| "Invalid input.".to_string());
+
+    HttpResponse::BadRequest().json(AppError {
+        error,
+        fields: Some(errors),
+    })
+}
+
+/// Runs the derived validations, rejects reserved slugs and normalizes `target`
+/// and the targets of `rules`, collecting every error
+fn validate_route<T: Validate>(
+    route: &T,
+    slug: Option<&str>,
+    target: &str,
+    rules: &RouteRules,
+    targets: &TargetPolicy,
+) -> Result<(String, RouteRules), ValidationErrors> {
+    let mut errors = route.validate().err().unwrap_or_default();
+    if slug.is_some_and(is_reserved) {
+        let mut error = ValidationError::new("reserved");
+        error.message = Some("This slug is reserved".into());
+        errors.add("slug", error);
+    }
+    let target = targets
+        .normalize(target)
+        .map_err(|error| errors.add("target", error));
+    let rules = rules
+        .normalized(targets)
+        .map_err(|error| errors.add("rules", error));
+    match (target, rules) {
+        (Ok(target), Ok(rules)) if errors.is_empty() => Ok((target, rules)),
+        _ => Err(errors),
+    }
+}
+
+/// Inserts the route, when no slug was given one is generated and retried on collisions
+async fn insert_route(
+    state: &AppState,
+    route: RouteData,
+    creator_id: Option<Uuid>,
+) -> Result<QueryResult<Route>, MailboxError> {
+    let new_route = |slug| CreateRoute {
+        slug,
+        creator_id,
+        target: route.target.clone(),
+        active: route.active,
+        active_from: route.active_from,
+        active_till: route.active_till,
+        rules: route.rules.clone(),
+    };
+
+    if let Some(slug) = route.slug.clone() {
+        return state.db.send(new_route(slug)).await;
+    }
+
+    let mut length = state.slug_length.load(Ordering::Relaxed);
+    for attempt in 0..MAX_ATTEMPTS {
+        if attempt > 0 && attempt % COLLISIONS_PER_GROWTH == 0 {
+            // the keyspace is getting crowded, start every later request longer too
+            length += 1;
+            state.slug_length.fetch_max(length, Ordering::Relaxed);
+        }
+        let slug = state.slugs.generate(length);
+        if is_reserved(&slug) {
+            continue;
+        }
+        match state.db.send(new_route(slug)).await {
+            Ok(Err(DatabaseError(UniqueViolation, _))) => continue,
+            result => return result,
+        }
+    }
+    // reported like a collision on the last attempt, see create_route
+    Ok(Err(DatabaseError(
+        UniqueViolation,
+        Box::new(format!("no free slug after {} attempts", MAX_ATTEMPTS)),
+    )))
+}
+
 #[post("/create")]
 async fn create_route(
+    req: HttpRequest,
     route: Json<RouteData>,
-    session: Session,
+    user: AuthUser,
     state: Data<AppState>,
 ) -> impl Responder {
-    let db = state.as_ref().db.clone();
-    let route = route.into_inner();
+    let mut route = route.into_inner();
 
-    let user_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
+    }
 
-    if user_id.is_none() {
-        return HttpResponse::Unauthorized().json("Unauthorized");
+    if state.require_verified_email {
+        match state.db.send(GetUser { id: user.id }).await {
+            Ok(Ok(owner)) if owner.email_verified => {}
+            Ok(Ok(_)) => {
+                return HttpResponse::Forbidden()
+                    .json("Verify your email address before creating routes")
+            }
+            Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+            _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+        }
     }
 
-    match db
-        .send(CreateRoute {
-            slug: route.slug,
-            creator_id: user_id,
-            target: route.target,
-            active: route.active,
-        })
-        .await
-    {
-        Ok(Ok(route)) => HttpResponse::Ok().json(route),
+    let (target, rules) = match validate_route(
+        &route,
+        route.slug.as_deref(),
+        &route.target,
+        &route.rules,
+        &state.targets,
+    ) {
+        Ok(normalized) => normalized,
+        Err(errors) => return validation_error(errors),
+    };
+    route.target = target;
+    route.rules = rules;
+
+    let generated = route.slug.is_none();
+    match insert_route(&state, route, Some(user.id)).await {
+        Ok(Ok(route)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_CREATE, TARGET_ROUTE, route.id)
+                .by(user.id)
+                .after(&route);
+            record(&state.db, &req, event).await;
+            HttpResponse::Ok().json(route)
+        }
         Ok(Err(error)) => match error {
+            DatabaseError(UniqueViolation, _) if generated => HttpResponse::ServiceUnavailable()
+                .json("Could not find a free slug, please try again"),
             DatabaseError(_, _) => {
                 HttpResponse::BadRequest().json("Route with this slug already exists")
             }
@@ -54,8 +187,7 @@ async fn create_orphan_route(
     session: Session,
     state: Data<AppState>,
 ) -> impl Responder {
-    let db = state.as_ref().db.clone();
-    let route = route.into_inner();
+    let mut route = route.into_inner();
 
     if session.get::<Uuid>("user_id").is_ok() {
         // User has a valid session and hence has an account so they should use /create
@@ -63,32 +195,36 @@ async fn create_orphan_route(
             "You are already a user, app should use /api/routes/create and not /create-orphan",
         );
     }
-    match db
-        .send(CreateRoute {
-            slug: route.slug,
-            creator_id: None,
-            target: route.target,
-            active: route.active,
-        })
-        .await
-    {
+
+    let (target, rules) = match validate_route(
+        &route,
+        route.slug.as_deref(),
+        &route.target,
+        &route.rules,
+        &state.targets,
+    ) {
+        Ok(normalized) => normalized,
+        Err(errors) => return validation_error(errors),
+    };
+    route.target = target;
+    route.rules = rules;
+
+    match insert_route(&state, route, None).await {
         Ok(Ok(route)) => HttpResponse::Ok().json(route),
         _ => HttpResponse::InternalServerError().json("Something went wrong"),
     }
 }
 #[get("/my")]
-async fn get_user_routes(session: Session, state: Data<AppState>) -> impl Responder {
+async fn get_user_routes(user: AuthUser, state: Data<AppState>) -> impl Responder {
     let db = state.as_ref().db.clone();
 
-    let user_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
-    if user_id.is_none() {
-        return HttpResponse::Unauthorized().json("Unauthorized");
+    if !user.has_scope(SCOPE_ROUTES_READ) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_READ);
     }
-    let user_id: Uuid = user_id.unwrap();
 
     match db
         .send(GetMyRoutes {
-            creator_id: user_id,
+            creator_id: user.id,
         })
         .await
     {
@@ -98,7 +234,8 @@ async fn get_user_routes(session: Session, state: Data<AppState>) -> impl Respon
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "validate_update_route_window"))]
 pub struct UpdateRouteData {
     /// ID of route to be updated
     id: Uuid,
@@ -108,34 +245,73 @@ pub struct UpdateRouteData {
     pub target: String,
     /// Is the link active
     pub active: bool,
+    /// Time (UTC) from which the link will be active
+    pub active_from: Option<NaiveDateTime>,
+    /// Time (UTC) till which the link should be active
+    pub active_till: Option<NaiveDateTime>,
+    /// Tried in order before the target, a missing list removes them
+    #[serde(default)]
+    pub rules: RouteRules,
+}
+
+fn validate_update_route_window(route: &UpdateRouteData) -> Result<(), ValidationError> {
+    validate_window(route.active_from, route.active_till)
 }
 
 #[put("/update")]
 async fn update_route(
-    session: Session,
+    req: HttpRequest,
+    user: AuthUser,
     route: Json<UpdateRouteData>,
     state: Data<AppState>,
 ) -> impl Responder {
     let db = state.as_ref().db.clone();
-    let route = route.into_inner();
+    let mut route = route.into_inner();
 
-    let user_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
-    if user_id.is_none() {
-        return HttpResponse::Unauthorized().json("Unauthorized");
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
     }
-    let user_id: Uuid = user_id.unwrap();
+
+    let (target, rules) = match validate_route(
+        &route,
+        Some(&route.slug),
+        &route.target,
+        &route.rules,
+        &state.targets,
+    ) {
+        Ok(normalized) => normalized,
+        Err(errors) => return validation_error(errors),
+    };
+    route.target = target;
+    route.rules = rules;
+
+    let before = match db.send(GetRoute { id: route.id }).await {
+        Ok(Ok(before)) if before.creator_id == Some(user.id) => before,
+        Ok(_) => return HttpResponse::NotFound().json("Route not found, or unauthorized access"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
 
     match db
         .send(UpdateRoute {
             id: route.id,
-            creator_id: user_id, // not updated but used to filter
+            creator_id: user.id, // not updated but used to filter
             slug: route.slug,
             target: route.target,
             active: route.active,
+            active_from: route.active_from,
+            active_till: route.active_till,
+            rules: route.rules,
         })
         .await
     {
-        Ok(Ok(route)) => HttpResponse::Ok().json(route),
+        Ok(Ok(route)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_UPDATE, TARGET_ROUTE, route.id)
+                .by(user.id)
+                .before(&before)
+                .after(&route);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(route)
+        }
         Ok(Err(_)) => HttpResponse::NotFound().json("Route not found, or unauthorized access"),
         _ => HttpResponse::InternalServerError().json("Something went wrong"),
     }
@@ -143,21 +319,184 @@ async fn update_route(
 
 #[delete("/delete/{id}")]
 async fn delete_route(
+    req: HttpRequest,
     Path(id): Path<Uuid>,
-    session: Session,
+    user: AuthUser,
     state: Data<AppState>,
 ) -> impl Responder {
     let db = state.as_ref().db.clone();
-    let creator_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
-    if creator_id.is_none() {
-        return HttpResponse::Unauthorized().json("Unauthorized");
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
     }
-    let creator_id: Uuid = creator_id.unwrap();
 
-    match db.send(DeleteRoute { id, creator_id }).await {
-        Ok(Ok(route)) => HttpResponse::Ok().json(route),
+    match db
+        .send(DeleteRoute {
+            id,
+            creator_id: user.id,
+        })
+        .await
+    {
+        Ok(Ok(route)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_DELETE, TARGET_ROUTE, route.id)
+                .by(user.id)
+                .before(&route);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(route)
+        }
         Ok(Err(_)) => HttpResponse::NotFound()
             .json("Route not found, or you are trying to access someone else's route"),
         _ => HttpResponse::InternalServerError().json("Something went wrong"),
     }
 }
+
+#[get("/{id}/history")]
+async fn get_route_history(
+    Path(id): Path<Uuid>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if !user.has_scope(SCOPE_ROUTES_READ) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_READ);
+    }
+
+    match db
+        .send(GetRouteHistory {
+            route_id: id,
+            creator_id: user.id,
+        })
+        .await
+    {
+        Ok(Ok(versions)) => HttpResponse::Ok().json(versions),
+        Ok(Err(_)) => HttpResponse::NotFound().json("Route not found, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// Restores slug, target, active flag, window and rules of an older version
+#[post("/{id}/rollback/{version}")]
+async fn rollback_route(
+    req: HttpRequest,
+    Path((id, version)): Path<(Uuid, i32)>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
+    }
+
+    let old = match db
+        .send(GetRouteVersion {
+            route_id: id,
+            creator_id: user.id,
+            version,
+        })
+        .await
+    {
+        Ok(Ok(old)) => old,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("Version not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    // rules may have changed since, e.g. a target domain was blocked
+    if is_reserved(&old.slug) {
+        return HttpResponse::BadRequest().json("The slug of this version is reserved now");
+    }
+    let mut errors = ValidationErrors::new();
+    if let Err(error) = state.targets.normalize(&old.target) {
+        errors.add("target", error);
+    }
+    if let Err(error) = old.rules.normalized(&state.targets) {
+        errors.add("rules", error);
+    }
+    if !errors.is_empty() {
+        return validation_error(errors);
+    }
+    let before = match db.send(GetRoute { id }).await {
+        Ok(Ok(before)) => before,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("Route not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+
+    match db
+        .send(RollbackRoute {
+            route_id: id,
+            creator_id: user.id,
+            version,
+        })
+        .await
+    {
+        Ok(Ok(route)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_ROLLBACK, TARGET_ROUTE, id)
+                .by(user.id)
+                .details(json!({ "version": version }))
+                .before(&before)
+                .after(&route);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(route)
+        }
+        Ok(Err(DatabaseError(UniqueViolation, _))) => {
+            HttpResponse::Conflict().json("Another route uses the slug of this version now")
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("Version not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// Longest time series a single stats request may ask for
+const MAX_STATS_BUCKETS: i64 = 5000;
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Start of the range (UTC), defaults to 30 days before `to`
+    pub from: Option<NaiveDateTime>,
+    /// End of the range (UTC, exclusive), defaults to now
+    pub to: Option<NaiveDateTime>,
+    /// Size of time series buckets, defaults to day
+    pub bucket: Option<StatsBucket>,
+    /// Length of the top referrers/browsers/... lists, defaults to 10
+    pub limit: Option<i64>,
+}
+
+#[get("/{id}/stats")]
+async fn get_route_stats(
+    Path(id): Path<Uuid>,
+    query: Query<StatsQuery>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let query = query.into_inner();
+    if !user.has_scope(SCOPE_STATS_READ) {
+        return AuthUser::missing_scope(SCOPE_STATS_READ);
+    }
+
+    let to = query.to.unwrap_or_else(|| Utc::now().naive_utc());
+    let from = query.from.unwrap_or(to - Duration::days(30));
+    let bucket = query.bucket.unwrap_or(StatsBucket::Day);
+    if from >= to {
+        return HttpResponse::BadRequest().json(AppError::new("from must be before to"));
+    }
+    if bucket.count(from, to) > MAX_STATS_BUCKETS {
+        return HttpResponse::BadRequest().json(AppError::new(
+            "Range too long for this bucket size, use a larger bucket",
+        ));
+    }
+
+    match db
+        .send(GetRouteStats {
+            route_id: id,
+            creator_id: user.id,
+            from,
+            to,
+            bucket,
+            limit: query.limit.unwrap_or(10).clamp(1, 100),
+        })
+        .await
+    {
+        Ok(Ok(stats)) => HttpResponse::Ok().json(stats),
+        Ok(Err(diesel::result::Error::NotFound)) => HttpResponse::NotFound()
+            .json("Route not found, or you are trying to access someone else's route"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
@@ -0,0 +1,136 @@
+use crate::actors::db::route_schedules::{
+    CreateRouteSchedule, DeleteRouteSchedule, GetRouteSchedules,
+};
+use crate::handlers::routes::validation_error;
+use crate::models::api_tokens::{SCOPE_ROUTES_READ, SCOPE_ROUTES_WRITE};
+use crate::models::audit::{
+    NewAuditEvent, ACTION_ROUTE_SCHEDULE_CREATE, ACTION_ROUTE_SCHEDULE_DELETE, TARGET_ROUTE,
+};
+use crate::models::route_schedules::{NewRouteSchedule, RouteScheduleData};
+use crate::models::AppState;
+use crate::utils::audit::record;
+use crate::utils::auth::AuthUser;
+use chrono::Utc;
+use serde_json::json;
+use validator::{Validate, ValidationError};
+
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse, Responder,
+};
+use uuid::Uuid;
+
+// Scheduled target changes of a route, mounted under /api/routes/
+
+#[get("/{id}/schedule")]
+async fn get_route_schedules(
+    Path(id): Path<Uuid>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if !user.has_scope(SCOPE_ROUTES_READ) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_READ);
+    }
+
+    match db
+        .send(GetRouteSchedules {
+            route_id: id,
+            creator_id: user.id,
+        })
+        .await
+    {
+        Ok(Ok(schedules)) => HttpResponse::Ok().json(schedules),
+        Ok(Err(_)) => HttpResponse::NotFound().json("Route not found, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/{id}/schedule")]
+async fn create_route_schedule(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    data: Json<RouteScheduleData>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let data = data.into_inner();
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
+    }
+
+    let mut errors = data.validate().err().unwrap_or_default();
+    if data.effective_at <= Utc::now().naive_utc() {
+        let mut error = ValidationError::new("effective_at");
+        error.message = Some("effective_at must be in the future".into());
+        errors.add("effective_at", error);
+    }
+    let target = match state.targets.normalize(&data.target) {
+        Ok(target) if errors.is_empty() => target,
+        Ok(_) => return validation_error(errors),
+        Err(error) => {
+            errors.add("target", error);
+            return validation_error(errors);
+        }
+    };
+
+    match db
+        .send(CreateRouteSchedule {
+            creator_id: user.id,
+            schedule: NewRouteSchedule {
+                route_id: id,
+                effective_at: data.effective_at,
+                target,
+                created_by: Some(user.id),
+            },
+        })
+        .await
+    {
+        Ok(Ok(schedule)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_SCHEDULE_CREATE, TARGET_ROUTE, id)
+                .by(user.id)
+                .details(json!({ "schedule_id": schedule.id }))
+                .after(&schedule);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(schedule)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("Route not found, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[delete("/{id}/schedule/{schedule_id}")]
+async fn delete_route_schedule(
+    req: HttpRequest,
+    Path((id, schedule_id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if !user.has_scope(SCOPE_ROUTES_WRITE) {
+        return AuthUser::missing_scope(SCOPE_ROUTES_WRITE);
+    }
+
+    match db
+        .send(DeleteRouteSchedule {
+            id: schedule_id,
+            route_id: id,
+            creator_id: user.id,
+        })
+        .await
+    {
+        Ok(Ok(schedule)) => {
+            let event = NewAuditEvent::new(ACTION_ROUTE_SCHEDULE_DELETE, TARGET_ROUTE, id)
+                .by(user.id)
+                .details(json!({ "schedule_id": schedule.id }))
+                .before(&schedule);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(schedule)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound()
+            .json("Schedule not found, already applied, or unauthorized access"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
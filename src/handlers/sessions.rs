@@ -0,0 +1,85 @@
+use crate::actors::db::user_sessions::{
+    DeleteAllUserSessions, DeleteUserSession, GetMyUserSessions,
+};
+use crate::models::user_sessions::SessionInfo;
+use crate::models::AppState;
+use crate::utils::auth::{sessions_expired_before, SessionUser};
+use actix_session::Session;
+
+use actix_web::{
+    delete, get,
+    web::{Data, Path},
+    HttpResponse, Responder,
+};
+use uuid::Uuid;
+
+// Sessions are managed with the session cookie only, like tokens
+
+#[get("")]
+async fn get_user_sessions(user: SessionUser, state: Data<AppState>) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    match db
+        .send(GetMyUserSessions {
+            user_id: user.id,
+            expired_before: sessions_expired_before(&state),
+        })
+        .await
+    {
+        Ok(Ok(sessions)) => HttpResponse::Ok().json(
+            sessions
+                .into_iter()
+                .map(|session| SessionInfo {
+                    current: Some(session.id) == user.session_id,
+                    session,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[delete("/{id}")]
+async fn revoke_session(
+    Path(id): Path<Uuid>,
+    user: SessionUser,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    match db
+        .send(DeleteUserSession {
+            id,
+            user_id: user.id,
+        })
+        .await
+    {
+        Ok(Ok(revoked)) => {
+            if user.session_id == Some(id) {
+                session.purge();
+            }
+            HttpResponse::Ok().json(revoked)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("Session not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// Logs out every session of the user, this one included
+#[delete("")]
+async fn revoke_all_sessions(
+    user: SessionUser,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    match db.send(DeleteAllUserSessions { user_id: user.id }).await {
+        Ok(Ok(_)) => {
+            session.purge();
+            HttpResponse::Ok().json(true)
+        }
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
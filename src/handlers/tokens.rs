@@ -0,0 +1,94 @@
+use crate::actors::db::api_tokens::{CreateApiToken, DeleteApiToken, GetMyApiTokens};
+use crate::handlers::routes::validation_error;
+use crate::models::api_tokens::{ApiTokenData, CreatedApiToken};
+use crate::models::audit::{
+    NewAuditEvent, ACTION_USER_TOKEN_CREATE, ACTION_USER_TOKEN_REVOKE, TARGET_API_TOKEN,
+};
+use crate::models::AppState;
+use crate::utils::audit::record;
+use crate::utils::auth::{SessionUser, TOKEN_PREFIX};
+use crate::utils::crypto::{random_token, sha256_hex};
+use validator::Validate;
+
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse, Responder,
+};
+use uuid::Uuid;
+
+// Tokens are managed with the session cookie only, so a leaked token can't mint more tokens
+
+#[post("/create")]
+async fn create_token(
+    req: HttpRequest,
+    token: Json<ApiTokenData>,
+    user: SessionUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let token = token.into_inner();
+    let user_id = user.id;
+
+    if let Err(errors) = token.validate() {
+        return validation_error(errors);
+    }
+
+    let plain = random_token(TOKEN_PREFIX, 40);
+    match db
+        .send(CreateApiToken {
+            user_id,
+            name: token.name,
+            token_hash: sha256_hex(plain.as_bytes()),
+            scopes: token.scopes,
+            expires_at: token.expires_at,
+        })
+        .await
+    {
+        Ok(Ok(details)) => {
+            let event = NewAuditEvent::new(ACTION_USER_TOKEN_CREATE, TARGET_API_TOKEN, details.id)
+                .by(user_id)
+                .after(&details);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(CreatedApiToken {
+                token: plain,
+                details,
+            })
+        }
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[get("/my")]
+async fn get_user_tokens(user: SessionUser, state: Data<AppState>) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let user_id = user.id;
+
+    match db.send(GetMyApiTokens { user_id }).await {
+        Ok(Ok(tokens)) => HttpResponse::Ok().json(tokens),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[delete("/revoke/{id}")]
+async fn revoke_token(
+    req: HttpRequest,
+    Path(id): Path<Uuid>,
+    user: SessionUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let user_id = user.id;
+
+    match db.send(DeleteApiToken { id, user_id }).await {
+        Ok(Ok(token)) => {
+            let event = NewAuditEvent::new(ACTION_USER_TOKEN_REVOKE, TARGET_API_TOKEN, id)
+                .by(user_id)
+                .before(&token);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(token)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("Token not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
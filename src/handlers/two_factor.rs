@@ -0,0 +1,346 @@
+use crate::actors::db::two_factor::{
+    DisableTotp, EnableTotp, SetTotpSecret, UseRecoveryCode, UseTotpStep,
+};
+use crate::actors::db::users::GetUser;
+use crate::handlers::users::{inactive_account, reactivate, require_reauth, ConfirmPasswordData};
+use crate::models::audit::{
+    NewAuditEvent, ACTION_USER_2FA_DISABLE, ACTION_USER_2FA_ENABLE, ACTION_USER_LOGIN, TARGET_USER,
+};
+use crate::models::users::User;
+use crate::models::AppState;
+use crate::utils::audit::record;
+use crate::utils::auth::{pending_reactivation, pending_session_user, start_session, SessionUser};
+use crate::utils::crypto::sha256_hex;
+use crate::utils::totp::{
+    encode_secret, generate_recovery_codes, generate_secret, matching_step,
+    normalize_recovery_code, provisioning_uri,
+};
+use actix_session::Session;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use actix_web::{
+    post,
+    web::{Data, Json},
+    HttpRequest, HttpResponse, Responder,
+};
+use uuid::Uuid;
+
+#[derive(Serialize)]
+/// What an authenticator app needs, shown once while enrolling
+struct Enrollment {
+    /// base32 secret for typing in by hand
+    secret: String,
+    /// otpauth:// URI for a QR code
+    uri: String,
+}
+
+#[derive(Serialize)]
+struct RecoveryCodes {
+    /// shown once, each works a single time instead of a TOTP code
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CodeData {
+    /// TOTP code, or a recovery code where one is accepted
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct DisableData {
+    current_password: Option<String>,
+    code: String,
+}
+
+fn not_configured() -> HttpResponse {
+    HttpResponse::ServiceUnavailable().json("Two-factor authentication is not available")
+}
+
+/// Shared by every place a code is typed, so none of them allows guessing
+fn throttle_key(user_id: Uuid) -> String {
+    format!("elide:login:2fa:{}", user_id)
+}
+
+/// Counts a code attempt of the user, the response to return when locked out
+async fn attempt_code(state: &AppState, user_id: Uuid) -> Result<(), HttpResponse> {
+    state
+        .login_throttle
+        .attempt(&[throttle_key(user_id)])
+        .await
+        .map_err(|wait| {
+            HttpResponse::TooManyRequests()
+                .header(
+                    "Retry-After",
+                    wait.as_secs_f64().ceil().max(1.0).to_string(),
+                )
+                .json("Too many wrong codes, try again later")
+        })
+}
+
+/// Accepts a TOTP code or an unused recovery code, each works only once
+async fn verify_second_factor(state: &AppState, user: &User, code: &str) -> Result<bool, ()> {
+    let cipher = state.totp_cipher.as_ref().ok_or(())?;
+    let secret = match user
+        .totp_secret
+        .as_deref()
+        .and_then(|sealed| cipher.open(sealed))
+    {
+        Some(secret) => secret,
+        None => {
+            error!("TOTP secret of {} can't be decrypted", user.id);
+            return Err(());
+        }
+    };
+
+    if let Some(step) = matching_step(&secret, code, Utc::now().timestamp()) {
+        return match state
+            .db
+            .send(UseTotpStep {
+                user_id: user.id,
+                step,
+            })
+            .await
+        {
+            Ok(Ok(fresh)) => Ok(fresh),
+            _ => Err(()),
+        };
+    }
+    match state
+        .db
+        .send(UseRecoveryCode {
+            user_id: user.id,
+            code_hash: sha256_hex(normalize_recovery_code(code).as_bytes()),
+        })
+        .await
+    {
+        Ok(Ok(used)) => Ok(used),
+        _ => Err(()),
+    }
+}
+
+#[post("/enroll")]
+async fn enroll_two_factor(
+    data: Option<Json<ConfirmPasswordData>>,
+    user_session: SessionUser,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let cipher = match &state.totp_cipher {
+        Some(cipher) => cipher,
+        None => return not_configured(),
+    };
+
+    let user = match db
+        .send(GetUser {
+            id: user_session.id,
+        })
+        .await
+    {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    if user.totp_enabled {
+        return HttpResponse::Conflict().json("Two-factor authentication is already enabled");
+    }
+    let current_password = data.map(|data| data.into_inner().current_password);
+    if let Err(response) = require_reauth(&state, &session, &user, current_password).await {
+        return response;
+    }
+
+    let secret = generate_secret();
+    match db
+        .send(SetTotpSecret {
+            user_id: user.id,
+            secret: cipher.seal(&secret),
+        })
+        .await
+    {
+        Ok(Ok(_)) => HttpResponse::Ok().json(Enrollment {
+            secret: encode_secret(&secret),
+            uri: provisioning_uri(&state.totp_issuer, &user.username, &secret),
+        }),
+        Ok(Err(_)) => HttpResponse::Conflict().json("Two-factor authentication is already enabled"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/confirm")]
+async fn confirm_two_factor(
+    req: HttpRequest,
+    data: Json<CodeData>,
+    user_session: SessionUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let cipher = match &state.totp_cipher {
+        Some(cipher) => cipher,
+        None => return not_configured(),
+    };
+
+    let user = match db
+        .send(GetUser {
+            id: user_session.id,
+        })
+        .await
+    {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    if user.totp_enabled {
+        return HttpResponse::Conflict().json("Two-factor authentication is already enabled");
+    }
+    let secret = match user
+        .totp_secret
+        .as_deref()
+        .and_then(|sealed| cipher.open(sealed))
+    {
+        Some(secret) => secret,
+        None => return HttpResponse::BadRequest().json("Enroll before confirming"),
+    };
+    if let Err(response) = attempt_code(&state, user.id).await {
+        return response;
+    }
+    let step = match matching_step(&secret, &data.code, Utc::now().timestamp()) {
+        Some(step) => step,
+        None => return HttpResponse::BadRequest().json("Invalid code"),
+    };
+    state.login_throttle.clear(&throttle_key(user.id)).await;
+
+    let codes = generate_recovery_codes();
+    match db
+        .send(EnableTotp {
+            user_id: user.id,
+            step,
+            recovery_codes: codes
+                .iter()
+                .map(|code| sha256_hex(normalize_recovery_code(code).as_bytes()))
+                .collect(),
+        })
+        .await
+    {
+        Ok(Ok(_)) => {
+            let event =
+                NewAuditEvent::new(ACTION_USER_2FA_ENABLE, TARGET_USER, user.id).by(user.id);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(RecoveryCodes {
+                recovery_codes: codes,
+            })
+        }
+        Ok(Err(_)) => HttpResponse::BadRequest().json("Enroll before confirming"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/disable")]
+async fn disable_two_factor(
+    req: HttpRequest,
+    data: Json<DisableData>,
+    user_session: SessionUser,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let data = data.into_inner();
+
+    let user = match db
+        .send(GetUser {
+            id: user_session.id,
+        })
+        .await
+    {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    if !user.totp_enabled {
+        return HttpResponse::BadRequest().json("Two-factor authentication is not enabled");
+    }
+    if let Err(response) = require_reauth(&state, &session, &user, data.current_password).await {
+        return response;
+    }
+    if let Err(response) = attempt_code(&state, user.id).await {
+        return response;
+    }
+
+    match verify_second_factor(&state, &user, &data.code).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::BadRequest().json("Invalid code"),
+        Err(_) if state.totp_cipher.is_none() => return not_configured(),
+        Err(_) => return HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+    state.login_throttle.clear(&throttle_key(user.id)).await;
+
+    match db.send(DisableTotp { user_id: user.id }).await {
+        Ok(Ok(user)) => {
+            let event =
+                NewAuditEvent::new(ACTION_USER_2FA_DISABLE, TARGET_USER, user.id).by(user.id);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(user)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("User not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// Second step of a login for users with 2FA, after `login_user` accepted the password
+#[post("/login/2fa")]
+async fn login_two_factor(
+    req: HttpRequest,
+    data: Json<CodeData>,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let user_id = match pending_session_user(&session) {
+        Some(user_id) => user_id,
+        None => return HttpResponse::Unauthorized().json("Log in with your password first"),
+    };
+
+    if let Err(response) = attempt_code(&state, user_id).await {
+        return response;
+    }
+
+    let user = match db.send(GetUser { id: user_id }).await {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::Unauthorized().json("Log in with your password first"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    // suspended between the two steps, deactivated accounts may be on their way back
+    let reactivating = pending_reactivation(&session);
+    if !user.active && (!reactivating || user.is_suspended()) {
+        session.purge();
+        return inactive_account(&user);
+    }
+
+    match verify_second_factor(&state, &user, &data.code).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::BadRequest().json("Invalid code"),
+        Err(_) if state.totp_cipher.is_none() => return not_configured(),
+        Err(_) => return HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+
+    state.login_throttle.clear(&throttle_key(user_id)).await;
+    let user = if !user.active {
+        match reactivate(&req, &state, &user).await {
+            Ok(user) => user,
+            Err(response) => return response,
+        }
+    } else {
+        user
+    };
+    match start_session(&req, &session, &state, &user).await {
+        Ok(_) => {
+            let event = NewAuditEvent::new(ACTION_USER_LOGIN, TARGET_USER, user.id)
+                .by(user.id)
+                .details(json!({ "second_factor": true }));
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(user)
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
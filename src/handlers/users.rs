@@ -1,14 +1,42 @@
-use crate::actors::db::users::{CreateUser, DeleteUser, GetUser, GetUserByUsername, UpdateUser};
+use crate::actors::db::audit::GetAuditEvents;
+use crate::actors::db::password_resets::{CompletePasswordReset, CreatePasswordReset};
+use crate::actors::db::user_sessions::DeleteUserSession;
+use crate::actors::db::users::{
+    CreateUser, DeactivateUser, DeleteUser, GetUser, GetUserByEmail, GetUserByUsername,
+    ReactivateUser, UpdateUser,
+};
+use crate::actors::db::verifications::{ConfirmEmail, CreateEmailVerification};
+use crate::actors::mailer::SendMail;
+use crate::models::api_tokens::SCOPE_USER_READ;
+use crate::models::audit::{
+    NewAuditEvent, ACTION_USER_DEACTIVATE, ACTION_USER_DELETE, ACTION_USER_LOGIN,
+    ACTION_USER_LOGIN_FAILED, ACTION_USER_LOGOUT, ACTION_USER_PASSWORD_RESET,
+    ACTION_USER_REACTIVATE, ACTION_USER_REGISTER, ACTION_USER_UPDATE, ACTION_USER_VERIFY_EMAIL,
+    TARGET_USER,
+};
+use crate::models::extras::Pagination;
+use crate::models::users::{AuditUserInfo, User};
+use crate::models::verifications::ConfirmOutcome;
 use crate::models::AppState;
-use crate::utils::crypto::{hash, verify};
+use crate::utils::audit::record;
+use crate::utils::auth::{
+    mark_reauthenticated, recently_reauthenticated, start_pending_session, start_session, AuthUser,
+    SessionUser, SESSION_ID, SESSION_USER_ID,
+};
+use crate::utils::crypto::{hash, random_code, random_token, sha256_hex, verify};
+use crate::utils::login_throttle::{ip_key, reauth_key, username_key};
+use crate::utils::mailer::Mail;
+use crate::utils::net::client_ip;
 use actix_session::Session;
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use validator::Validate;
 
 use actix_web::{
     delete, get, post, put,
-    web::{Data, Json},
-    HttpResponse, Responder,
+    web::{Data, Json, Query},
+    HttpRequest, HttpResponse, Responder,
 };
 use uuid::Uuid;
 
@@ -43,6 +71,14 @@ pub struct UpdateUserData {
     /// email id of user
     #[validate(email)]
     pub email: Option<String>,
+    /// needed to change username, password or email unless confirmed recently
+    pub current_password: Option<String>,
+}
+
+#[derive(Deserialize)]
+/// Proof that the request comes from someone who knows the password
+pub struct ConfirmPasswordData {
+    pub current_password: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -51,12 +87,112 @@ struct UserLoginData {
     password: String,
 }
 
+#[derive(Serialize)]
+/// Answer to a correct password when the account has 2FA, POST the code to /login/2fa
+struct TwoFactorRequired {
+    two_factor_required: bool,
+}
+
+#[derive(Deserialize)]
+struct VerifyEmailData {
+    code: String,
+}
+
+#[derive(Deserialize, Validate)]
+struct PasswordResetRequest {
+    #[validate(email)]
+    email: String,
+}
+
+#[derive(Deserialize, Validate)]
+struct PasswordResetData {
+    token: String,
+    /// the new password
+    #[validate(length(min = 6))]
+    password: String,
+}
+
+/// Digits in an email verification code
+const VERIFICATION_CODE_DIGITS: u32 = 6;
+
+// codes are short, keying the hash with the user id at least keeps equal codes
+// of different users apart; guessing is limited by the attempt counter
+fn verification_code_hash(user_id: Uuid, code: &str) -> String {
+    sha256_hex(format!("{}:{}", user_id, code.trim()).as_bytes())
+}
+
+/// Mails a fresh verification code to the user's current address.
+/// Ok(false) when the previous code was sent too recently
+async fn send_verification(state: &AppState, user: &User) -> Result<bool, ()> {
+    let code = random_code(VERIFICATION_CODE_DIGITS);
+    let ttl = Duration::minutes(state.verification_code_ttl);
+
+    match state
+        .db
+        .send(CreateEmailVerification {
+            user_id: user.id,
+            email: user.email.clone(),
+            code_hash: verification_code_hash(user.id, &code),
+            expires_at: Utc::now().naive_utc() + ttl,
+        })
+        .await
+    {
+        Ok(Ok(Some(_))) => {}
+        Ok(Ok(None)) => return Ok(false),
+        _ => return Err(()),
+    }
+
+    state.mailer.do_send(SendMail(Mail {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nyour verification code is {}\n\nIt expires in {} minutes.\n",
+            user.name,
+            code,
+            ttl.num_minutes()
+        ),
+    }));
+    Ok(true)
+}
+
+/// Lets a sensitive change through when `current_password` is right or the
+/// password was confirmed recently, so a stolen cookie alone can't take over the account.
+/// Wrong passwords are throttled per user like logins
+pub(crate) async fn require_reauth(
+    state: &AppState,
+    session: &Session,
+    user: &User,
+    current_password: Option<String>,
+) -> Result<(), HttpResponse> {
+    match current_password {
+        Some(password) => {
+            let throttle = &state.login_throttle;
+            let key = reauth_key(user.id);
+            throttle
+                .attempt(std::slice::from_ref(&key))
+                .await
+                .map_err(too_many_attempts)?;
+            if !verify(&user.password_hash, password) {
+                return Err(HttpResponse::Forbidden().json("Current password is wrong"));
+            }
+            throttle.clear(&key).await;
+            mark_reauthenticated(session)
+                .map_err(|_| HttpResponse::InternalServerError().json("Something went wrong"))
+        }
+        None if recently_reauthenticated(session) => Ok(()),
+        None => Err(HttpResponse::Forbidden().json("Confirm your current password to continue")),
+    }
+}
+
 #[post("/register")]
-async fn register_user(user: Json<UserData>, state: Data<AppState>) -> impl Responder {
+async fn register_user(
+    req: HttpRequest,
+    user: Json<UserData>,
+    state: Data<AppState>,
+) -> impl Responder {
     let db = state.as_ref().db.clone();
     let user = user.into_inner();
     info!("User is registering");
-    info!("{:?}", user);
 
     if let Err(errors) = user.validate() {
         let error_map = errors.field_errors();
@@ -85,22 +221,29 @@ async fn register_user(user: Json<UserData>, state: Data<AppState>) -> impl Resp
         })
         .await
     {
-        Ok(Ok(user)) => HttpResponse::Ok().json(user),
+        Ok(Ok(user)) => {
+            let event = NewAuditEvent::new(ACTION_USER_REGISTER, TARGET_USER, user.id)
+                .by(user.id)
+                .after(&AuditUserInfo::from(&user));
+            record(&db, &req, event).await;
+            // the account exists either way, the code can be requested again
+            if send_verification(&state, &user).await.is_err() {
+                error!("could not create an email verification for {}", user.id);
+            }
+            HttpResponse::Ok().json(user)
+        }
         _ => HttpResponse::InternalServerError().json("Something went wrong"),
     }
 }
 
 #[get("/me")]
-async fn me_user(session: Session, state: Data<AppState>) -> impl Responder {
+async fn me_user(user: AuthUser, state: Data<AppState>) -> impl Responder {
     let db = state.as_ref().db.clone();
-    let user_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
-
-    if user_id.is_none() {
-        return HttpResponse::Unauthorized().json("Unauthorized");
+    if !user.has_scope(SCOPE_USER_READ) {
+        return AuthUser::missing_scope(SCOPE_USER_READ);
     }
-    let user_id = user_id.unwrap();
 
-    match db.send(GetUser { id: user_id }).await {
+    match db.send(GetUser { id: user.id }).await {
         Ok(Ok(user)) => HttpResponse::Ok().json(user),
         Ok(Err(_)) => HttpResponse::NotFound().json("User not found"),
         _ => HttpResponse::InternalServerError().json("Something went wrong"),
@@ -118,32 +261,93 @@ async fn me_user(session: Session, state: Data<AppState>) -> impl Responder {
 
 #[post("/login")]
 async fn login_user(
+    req: HttpRequest,
     login_data: Json<UserLoginData>,
     session: Session,
     state: Data<AppState>,
 ) -> impl Responder {
-    info!("{:?}", login_data);
     let db = state.as_ref().db.clone();
+    let throttle = &state.login_throttle;
     let login_data = login_data.into_inner();
-    match db
+
+    // a username under attack is locked from everywhere, an attacking IP for every username
+    let user_key = username_key(&login_data.username);
+    let address_key = client_ip(&req).map(|ip| ip_key(&ip));
+    let keys: Vec<String> = Some(user_key.clone())
+        .into_iter()
+        .chain(address_key.clone())
+        .collect();
+    if let Err(wait) = throttle.attempt(&keys).await {
+        return too_many_attempts(wait);
+    }
+
+    let user = match db
         .send(GetUserByUsername {
             username: login_data.username,
         })
         .await
     {
-        Ok(Ok(user)) => {
-            if verify(&user.password_hash, login_data.password) {
-                session.set("user_id", user.id).unwrap();
-                HttpResponse::Ok().json(user)
-            } else {
-                // prevent brute forcing usernames
-                HttpResponse::NotFound().json("Username or password wrong")
+        Ok(Ok(user)) if verify(&user.password_hash, login_data.password.clone()) => user,
+        // unknown usernames count too, so lockouts don't reveal which ones exist
+        Ok(result) => {
+            if let Ok(user) = result {
+                let event = NewAuditEvent::new(ACTION_USER_LOGIN_FAILED, TARGET_USER, user.id)
+                    .concerning(user.id);
+                record(&db, &req, event).await;
             }
+            // prevent brute forcing usernames
+            return HttpResponse::NotFound().json("Username or password wrong");
         }
-        // prevent brute forcing usernames
-        Ok(Err(_err)) => HttpResponse::NotFound().json("Username or password wrong"),
-        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+
+    // the IP keeps its earlier attempts, logging into an own account must not unlock
+    // guessing others
+    throttle.clear(&user_key).await;
+    if let Some(key) = &address_key {
+        throttle.refund(key).await;
+    }
+    if !user.active {
+        return inactive_account(&user);
+    }
+    if user.totp_enabled {
+        return match start_pending_session(&session, user.id, false) {
+            Ok(_) => HttpResponse::Accepted().json(TwoFactorRequired {
+                two_factor_required: true,
+            }),
+            Err(_) => HttpResponse::InternalServerError().json("Something went wrong"),
+        };
     }
+    match start_session(&req, &session, &state, &user).await {
+        Ok(_) => {
+            let event = NewAuditEvent::new(ACTION_USER_LOGIN, TARGET_USER, user.id)
+                .by(user.id)
+                .details(json!({ "second_factor": false }));
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(user)
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// 403 for a user whose password checked out but who may not log in
+pub(crate) fn inactive_account(user: &User) -> HttpResponse {
+    match &user.suspension_reason {
+        Some(reason) if user.is_suspended() => {
+            HttpResponse::Forbidden().json(format!("Account suspended: {}", reason))
+        }
+        _ if user.is_suspended() => HttpResponse::Forbidden().json("Account suspended"),
+        _ => HttpResponse::Forbidden().json("Account deactivated, reactivate it to log in"),
+    }
+}
+
+fn too_many_attempts(wait: std::time::Duration) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .header(
+            "Retry-After",
+            wait.as_secs_f64().ceil().max(1.0).to_string(),
+        )
+        .json("Too many failed logins, try again later")
 }
 
 #[get("/loggedin")]
@@ -156,25 +360,35 @@ async fn loggedin_check(session: Session) -> impl Responder {
 }
 
 #[get("/logout")]
-async fn logout_user(session: Session) -> impl Responder {
+async fn logout_user(req: HttpRequest, session: Session, state: Data<AppState>) -> impl Responder {
+    let user_id: Option<Uuid> = session.get(SESSION_USER_ID).unwrap_or(None);
+    let session_id: Option<Uuid> = session.get(SESSION_ID).unwrap_or(None);
     session.purge();
+
+    if let (Some(user_id), Some(id)) = (user_id, session_id) {
+        // the cookie is gone either way, a stale row only shows up in the session list
+        if let Ok(Err(err)) = state.db.send(DeleteUserSession { id, user_id }).await {
+            warn!("could not delete session {}: {}", id, err);
+        }
+    }
+    if let Some(user_id) = user_id {
+        let event = NewAuditEvent::new(ACTION_USER_LOGOUT, TARGET_USER, user_id).by(user_id);
+        record(&state.db, &req, event).await;
+    }
     HttpResponse::Ok().json(true)
 }
 
 #[put("/update")]
 async fn update_user(
+    req: HttpRequest,
     user: Json<UpdateUserData>,
+    user_session: SessionUser,
     session: Session,
     state: Data<AppState>,
 ) -> impl Responder {
     let db = state.as_ref().db.clone();
     let user = user.into_inner();
-    let user_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
-
-    if user_id.is_none() {
-        return HttpResponse::Unauthorized().json("Unauthorized");
-    }
-    let user_id = user_id.unwrap();
+    let user_id = user_session.id;
 
     if let Err(errors) = user.validate() {
         let error_map = errors.field_errors();
@@ -195,6 +409,42 @@ async fn update_user(
         return HttpResponse::BadRequest().json(message);
     }
 
+    let current = match db.send(GetUser { id: user_id }).await {
+        Ok(Ok(current)) => current,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    let email_changed = user
+        .email
+        .as_ref()
+        .is_some_and(|email| *email != current.email);
+    let username_changed = user
+        .username
+        .as_ref()
+        .is_some_and(|username| *username != current.username);
+
+    let name_changed = user.name.as_ref().is_some_and(|name| *name != current.name);
+    let password_changed = user.password.is_some();
+    if email_changed || username_changed || password_changed {
+        if let Err(response) =
+            require_reauth(&state, &session, &current, user.current_password).await
+        {
+            return response;
+        }
+    }
+
+    // only which fields changed, the values would stay in the audit trail for good
+    let changed = [
+        ("name", name_changed),
+        ("username", username_changed),
+        ("email", email_changed),
+        ("password", password_changed),
+    ]
+    .iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(field, _)| *field)
+    .collect::<Vec<_>>();
+
     match db
         .send(UpdateUser {
             id: user_id,
@@ -205,28 +455,391 @@ async fn update_user(
                 .password
                 // remove null character to store in postgres
                 .map(|pass| hash(pass).0.trim_matches(char::from(0)).to_string()),
+            email_verified: if email_changed { Some(false) } else { None },
         })
         .await
     {
-        Ok(Ok(user)) => HttpResponse::Ok().json(user),
+        Ok(Ok(user)) => {
+            let event = NewAuditEvent::new(ACTION_USER_UPDATE, TARGET_USER, user_id)
+                .by(user_id)
+                .details(json!({ "changed": changed }))
+                .before(&AuditUserInfo::from(&current))
+                .after(&AuditUserInfo::from(&user));
+            record(&db, &req, event).await;
+            // the new password revoked every session, this one carries on
+            if password_changed && start_session(&req, &session, &state, &user).await.is_err() {
+                session.purge();
+            }
+            if email_changed && send_verification(&state, &user).await.is_err() {
+                error!("could not create an email verification for {}", user.id);
+            }
+            HttpResponse::Ok().json(user)
+        }
         Ok(Err(_)) => HttpResponse::NotFound().json("User not found"),
         _ => HttpResponse::InternalServerError().json("Something went wrong"),
     }
 }
 
+#[post("/verify-email")]
+async fn verify_email(
+    req: HttpRequest,
+    data: Json<VerifyEmailData>,
+    user_session: SessionUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let user_id = user_session.id;
+
+    match db
+        .send(ConfirmEmail {
+            user_id,
+            code_hash: verification_code_hash(user_id, &data.code),
+        })
+        .await
+    {
+        Ok(Ok(ConfirmOutcome::Verified)) => {
+            let event =
+                NewAuditEvent::new(ACTION_USER_VERIFY_EMAIL, TARGET_USER, user_id).by(user_id);
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(true)
+        }
+        Ok(Ok(ConfirmOutcome::Invalid)) => HttpResponse::BadRequest().json("Invalid code"),
+        Ok(Ok(ConfirmOutcome::Expired)) => {
+            HttpResponse::Gone().json("Code expired, request a new one")
+        }
+        Ok(Err(diesel::result::Error::NotFound)) => HttpResponse::NotFound().json("User not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/verify-email/resend")]
+async fn resend_verification(user_session: SessionUser, state: Data<AppState>) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let user_id = user_session.id;
+
+    let user = match db.send(GetUser { id: user_id }).await {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    if user.email_verified {
+        return HttpResponse::BadRequest().json("Email is already verified");
+    }
+
+    match send_verification(&state, &user).await {
+        Ok(true) => HttpResponse::Ok().json(true),
+        Ok(false) => HttpResponse::TooManyRequests()
+            .json("A code was sent recently, wait a minute before asking again"),
+        Err(_) => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/reauth")]
+async fn reauth_user(
+    data: Json<ConfirmPasswordData>,
+    user_session: SessionUser,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    let user = match db
+        .send(GetUser {
+            id: user_session.id,
+        })
+        .await
+    {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    match require_reauth(
+        &state,
+        &session,
+        &user,
+        Some(data.into_inner().current_password),
+    )
+    .await
+    {
+        Ok(_) => HttpResponse::Ok().json(true),
+        Err(response) => response,
+    }
+}
+
 #[delete("/delete")]
-async fn delete_user(session: Session, state: Data<AppState>) -> impl Responder {
+async fn delete_user(
+    req: HttpRequest,
+    data: Option<Json<ConfirmPasswordData>>,
+    user_session: SessionUser,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
     let db = state.as_ref().db.clone();
-    let user_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
+    let user_id = user_session.id;
 
-    if user_id.is_none() {
-        return HttpResponse::Unauthorized().json("Unauthorized");
+    let user = match db.send(GetUser { id: user_id }).await {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    let current_password = data.map(|data| data.into_inner().current_password);
+    if let Err(response) = require_reauth(&state, &session, &user, current_password).await {
+        return response;
     }
-    let user_id = user_id.unwrap();
 
     match db.send(DeleteUser { id: user_id }).await {
-        Ok(Ok(user)) => HttpResponse::Ok().json(user),
+        Ok(Ok(user)) => {
+            // the account is gone, so the event can't point at it as actor
+            let event = NewAuditEvent::new(ACTION_USER_DELETE, TARGET_USER, user_id)
+                .before(&AuditUserInfo::from(&user));
+            record(&db, &req, event).await;
+            HttpResponse::Ok().json(user)
+        }
+        Ok(Err(_)) => HttpResponse::NotFound().json("User not found"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// Deactivates the account until its owner reactivates it, routes stop resolving
+/// as `accounts.inactive_owner_routes` says
+#[post("/deactivate")]
+async fn deactivate_user(
+    req: HttpRequest,
+    data: Option<Json<ConfirmPasswordData>>,
+    user_session: SessionUser,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let user_id = user_session.id;
+
+    let user = match db.send(GetUser { id: user_id }).await {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::NotFound().json("User not found"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    let current_password = data.map(|data| data.into_inner().current_password);
+    if let Err(response) = require_reauth(&state, &session, &user, current_password).await {
+        return response;
+    }
+
+    match db
+        .send(DeactivateUser {
+            id: user_id,
+            audit: None,
+        })
+        .await
+    {
+        Ok(Ok(deactivated)) => {
+            let event = NewAuditEvent::new(ACTION_USER_DEACTIVATE, TARGET_USER, user_id)
+                .by(user_id)
+                .before(&AuditUserInfo::from(&user))
+                .after(&AuditUserInfo::from(&deactivated));
+            record(&db, &req, event).await;
+            session.purge();
+            HttpResponse::Ok().json(deactivated)
+        }
         Ok(Err(_)) => HttpResponse::NotFound().json("User not found"),
         _ => HttpResponse::InternalServerError().json("Something went wrong"),
     }
 }
+
+/// Reactivates a deactivated account once the user proved who they are
+pub(crate) async fn reactivate(
+    req: &HttpRequest,
+    state: &AppState,
+    user: &User,
+) -> Result<User, HttpResponse> {
+    match state.db.send(ReactivateUser { id: user.id }).await {
+        Ok(Ok(reactivated)) => {
+            let event = NewAuditEvent::new(ACTION_USER_REACTIVATE, TARGET_USER, user.id)
+                .by(user.id)
+                .before(&AuditUserInfo::from(user))
+                .after(&AuditUserInfo::from(&reactivated));
+            record(&state.db, req, event).await;
+            Ok(reactivated)
+        }
+        // suspended in the meantime
+        Ok(Err(_)) => Err(HttpResponse::Forbidden().json("Account suspended")),
+        _ => Err(HttpResponse::InternalServerError().json("Something went wrong")),
+    }
+}
+
+/// Undoes `deactivate_user` and logs in, suspended accounts stay locked
+#[post("/reactivate")]
+async fn reactivate_user(
+    req: HttpRequest,
+    login_data: Json<UserLoginData>,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let throttle = &state.login_throttle;
+    let login_data = login_data.into_inner();
+
+    // shares the login counters, otherwise this is a second door for guessing passwords
+    let user_key = username_key(&login_data.username);
+    let address_key = client_ip(&req).map(|ip| ip_key(&ip));
+    let keys: Vec<String> = Some(user_key.clone())
+        .into_iter()
+        .chain(address_key.clone())
+        .collect();
+    if let Err(wait) = throttle.attempt(&keys).await {
+        return too_many_attempts(wait);
+    }
+
+    let user = match db
+        .send(GetUserByUsername {
+            username: login_data.username,
+        })
+        .await
+    {
+        Ok(Ok(user)) if verify(&user.password_hash, login_data.password.clone()) => user,
+        Ok(_) => return HttpResponse::NotFound().json("Username or password wrong"),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+    throttle.clear(&user_key).await;
+    if let Some(key) = &address_key {
+        throttle.refund(key).await;
+    }
+
+    if user.active {
+        return HttpResponse::BadRequest().json("Account is already active");
+    }
+    if user.is_suspended() {
+        return inactive_account(&user);
+    }
+    // the account stays deactivated until the second factor is in, see login_two_factor
+    if user.totp_enabled {
+        return match start_pending_session(&session, user.id, true) {
+            Ok(_) => HttpResponse::Accepted().json(TwoFactorRequired {
+                two_factor_required: true,
+            }),
+            Err(_) => HttpResponse::InternalServerError().json("Something went wrong"),
+        };
+    }
+
+    let user = match reactivate(&req, &state, &user).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    match start_session(&req, &session, &state, &user).await {
+        Ok(_) => HttpResponse::Ok().json(user),
+        Err(_) => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+#[post("/password-reset")]
+async fn request_password_reset(
+    data: Json<PasswordResetRequest>,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    if data.validate().is_err() {
+        return HttpResponse::BadRequest().json(format!("Invalid email address '{}'", data.email));
+    }
+
+    // answer the same whether or not the address is known, so it can't be used
+    // to find out who has an account
+    let user = match db
+        .send(GetUserByEmail {
+            email: data.into_inner().email,
+        })
+        .await
+    {
+        Ok(Ok(user)) => user,
+        Ok(Err(_)) => return HttpResponse::Ok().json(true),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    };
+
+    let token = random_token("", 40);
+    let ttl = Duration::minutes(state.password_reset_ttl);
+    match db
+        .send(CreatePasswordReset {
+            user_id: user.id,
+            token_hash: sha256_hex(token.as_bytes()),
+            expires_at: Utc::now().naive_utc() + ttl,
+        })
+        .await
+    {
+        Ok(Ok(Some(_))) => {}
+        Ok(Ok(None)) => return HttpResponse::Ok().json(true),
+        _ => return HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+
+    state.mailer.do_send(SendMail(Mail {
+        to: user.email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nuse this link to choose a new password:\n{}/reset-password?token={}\n\n\
+             It expires in {} minutes. If you did not ask for it, ignore this mail.\n",
+            user.name,
+            state.console_url.trim_end_matches('/'),
+            token,
+            ttl.num_minutes()
+        ),
+    }));
+    HttpResponse::Ok().json(true)
+}
+
+#[post("/password-reset/complete")]
+async fn complete_password_reset(
+    req: HttpRequest,
+    data: Json<PasswordResetData>,
+    session: Session,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+    let data = data.into_inner();
+    if data.validate().is_err() {
+        return HttpResponse::BadRequest().json("Invalid password. Too short");
+    }
+
+    match db
+        .send(CompletePasswordReset {
+            token_hash: sha256_hex(data.token.trim().as_bytes()),
+            // remove null character to store in postgres
+            password_hash: hash(data.password)
+                .0
+                .trim_matches(char::from(0))
+                .to_string(),
+        })
+        .await
+    {
+        Ok(Ok(Some(user))) => {
+            let event =
+                NewAuditEvent::new(ACTION_USER_PASSWORD_RESET, TARGET_USER, user.id).by(user.id);
+            record(&db, &req, event).await;
+            // every other session is revoked by the new epoch, drop this one too
+            session.purge();
+            HttpResponse::Ok().json(true)
+        }
+        Ok(Ok(None)) => HttpResponse::BadRequest().json("Invalid or expired reset token"),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
+
+/// The user's own audit trail, newest first, including what admins did to their
+/// account and routes
+#[get("/audit")]
+async fn get_user_audit(
+    page: Query<Pagination>,
+    user: SessionUser,
+    state: Data<AppState>,
+) -> impl Responder {
+    let db = state.as_ref().db.clone();
+
+    match db
+        .send(GetAuditEvents {
+            actor_id: None,
+            user_id: Some(user.id),
+            target_id: None,
+            limit: page.limit(),
+            offset: page.offset(),
+        })
+        .await
+    {
+        Ok(Ok(events)) => HttpResponse::Ok().json(events),
+        _ => HttpResponse::InternalServerError().json("Something went wrong"),
+    }
+}
@@ -1,3 +1,6 @@
+// diesel 1.x derives and table! expand to impls inside function bodies
+#![allow(non_local_definitions)]
+
 extern crate actix;
 #[macro_use]
 extern crate diesel;
@@ -12,7 +15,9 @@ extern crate validator;
 extern crate log;
 
 mod actors;
+mod config;
 mod handlers;
+mod middleware;
 mod models;
 mod schema;
 mod utils;
@@ -21,50 +26,136 @@ use actix_web::{middleware::Logger, web::scope, App, HttpServer};
 
 // access logs are printed with the INFO level so ensure it is enabled by default
 
-use actix::SyncArbiter;
+use actix::{Actor, SyncArbiter};
 use actix_cors::Cors;
-use actix_redis::RedisSession;
-use std::env;
+use actix_redis::{RedisActor, RedisSession};
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
 
+use actors::click_writer::{ClickWriter, Flush};
 use actors::db::DbActor;
+use actors::mailer::MailActor;
+use actors::route_scheduler::RouteScheduler;
+use config::Config;
+use middleware::session_keys::{KeyRotation, SessionKeys};
 use models::AppState;
 use utils::{
     crypto::random_redis_key,
     db::{get_pool, run_migrations},
+    login_throttle::LoginThrottle,
+    net, reserved,
 };
 
 use handlers::{
+    admin::{
+        audit_trail, deactivate_account, disable_route, enable_route, reassign_route,
+        search_routes, search_users, suspend_user, system_stats, unsuspend_user,
+    },
     availability::{email_availability, slug_availability, username_availability},
     redirects::{redirect_by_slug, redirect_to_console},
-    routes::{create_route, delete_route, get_user_routes, update_route},
-    users::{delete_user, login_user, logout_user, me_user, register_user, update_user},
+    route_targets::{
+        create_route_target, delete_route_target, get_route_targets, update_route_target,
+    },
+    routes::{
+        create_route, delete_route, get_route_history, get_route_stats, get_user_routes,
+        rollback_route, update_route,
+    },
+    schedules::{create_route_schedule, delete_route_schedule, get_route_schedules},
+    sessions::{get_user_sessions, revoke_all_sessions, revoke_session},
+    tokens::{create_token, get_user_tokens, revoke_token},
+    two_factor::{confirm_two_factor, disable_two_factor, enroll_two_factor, login_two_factor},
+    users::{
+        complete_password_reset, deactivate_user, delete_user, get_user_audit, login_user,
+        logout_user, me_user, reactivate_user, reauth_user, register_user, request_password_reset,
+        resend_verification, update_user, verify_email,
+    },
 };
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let db_url = env::var("DATABASE_URL").expect("Error retrieving the database url");
-    run_migrations(&db_url);
-    let pool = get_pool(&db_url);
-    let db_addr = SyncArbiter::start(1, move || DbActor(pool.clone()));
-    let redis_key = random_redis_key(); // fetch redis key here so all threads have same key
     std::env::set_var("RUST_LOG", "info");
     env_logger::init();
 
+    let config = Config::load().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+
+    run_migrations(&config.database.url);
+    let pool = get_pool(&config.database.url, config.database.pool_size);
+    let db_addr = SyncArbiter::start(config.database.actors, move || DbActor(pool.clone()));
+    let click_writer = ClickWriter::new(
+        db_addr.clone(),
+        config.clicks.flush_interval(),
+        config.clicks.batch_size,
+        config.clicks.buffer_capacity,
+    )
+    .start();
+    RouteScheduler::new(db_addr.clone(), config.schedules.apply_interval()).start();
+    // checked by Config::load
+    let mailer = config.mailer().unwrap();
+    let mail_addr = SyncArbiter::start(1, move || MailActor(mailer.clone()));
+    // fetch redis key here so all threads have same key
+    let session_keys = config.session_keys().unwrap_or_else(|| SessionKeys {
+        primary: random_redis_key().to_vec(),
+        old: Vec::new(),
+    });
+    reserved::init(&config.slugs.reserved, &config.slugs.allowed);
+    // these were checked by Config::load
+    net::init(config.trusted_proxies().unwrap());
+    let slugs = config.slug_generator().unwrap();
+    let slug_length = Arc::new(AtomicUsize::new(slugs.length()));
+    let targets = config.target_policy().unwrap();
+    let geoip = config.geoip();
+    let ip_salt = config.clicks.ip_salt.clone();
+    let console_url = config.server.console_url.clone();
+    let redis_address = config.redis.address.clone();
+    let cors_origins = config.cors.allowed_origins.clone();
+    let cors_max_age = config.cors.max_age;
+    let require_verified_email = config.accounts.require_verified_email;
+    let verification_code_ttl = config.accounts.verification_code_ttl;
+    let password_reset_ttl = config.accounts.password_reset_ttl;
+    let session_ttl = config.session.ttl;
+    let totp_cipher = config.totp_cipher();
+    let totp_issuer = config.two_factor.issuer.clone();
+    let inactive_owner_routes = config.accounts.inactive_owner_routes;
+    let login_throttle = LoginThrottle::new(
+        config.login.throttle_policy(),
+        if config.login.use_redis() {
+            Some(RedisActor::start(redis_address.as_str()))
+        } else {
+            None
+        },
+    );
+
     info!("starting up");
 
-    HttpServer::new(move || {
-        let cors = Cors::permissive().max_age(3600 * 24 * 30); // 30 days
+    let clicks_addr = click_writer.clone();
+    let server = HttpServer::new(move || {
+        let cors = if cors_origins.is_empty() {
+            Cors::permissive()
+        } else {
+            cors_origins
+                .iter()
+                .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+                .allow_any_method()
+                .allow_any_header()
+                .supports_credentials()
+        }
+        .max_age(cors_max_age);
 
         App::new()
             .wrap(cors)
             .wrap(Logger::default())
             // cookie session middleware
             .wrap(
-                // TODO: no magic, just config
-                RedisSession::new("redis:6379", &redis_key)
+                RedisSession::new(redis_address.as_str(), &session_keys.primary)
+                    .ttl(session_ttl)
                     // don't allow the cookie to be accessed from javascript
                     .cookie_http_only(true),
             )
+            // must wrap the session middleware so it sees the re-signed cookie
+            .wrap(KeyRotation::new(&session_keys))
             .service(
                 scope("/api/")
                     .service(
@@ -72,16 +163,66 @@ async fn main() -> std::io::Result<()> {
                             .service(create_route)
                             .service(get_user_routes)
                             .service(update_route)
-                            .service(delete_route),
+                            .service(delete_route)
+                            .service(get_route_stats)
+                            .service(get_route_history)
+                            .service(rollback_route)
+                            .service(get_route_schedules)
+                            .service(create_route_schedule)
+                            .service(delete_route_schedule)
+                            .service(get_route_targets)
+                            .service(create_route_target)
+                            .service(update_route_target)
+                            .service(delete_route_target),
                     )
                     .service(
                         scope("/users/")
                             .service(register_user)
                             .service(me_user)
                             .service(login_user)
+                            .service(login_two_factor)
                             .service(logout_user)
+                            .service(reauth_user)
                             .service(update_user)
-                            .service(delete_user),
+                            .service(delete_user)
+                            .service(deactivate_user)
+                            .service(reactivate_user)
+                            .service(verify_email)
+                            .service(resend_verification)
+                            .service(request_password_reset)
+                            .service(complete_password_reset)
+                            .service(get_user_audit)
+                            .service(
+                                scope("/sessions")
+                                    .service(get_user_sessions)
+                                    .service(revoke_all_sessions)
+                                    .service(revoke_session),
+                            )
+                            .service(
+                                scope("/2fa/")
+                                    .service(enroll_two_factor)
+                                    .service(confirm_two_factor)
+                                    .service(disable_two_factor),
+                            ),
+                    )
+                    .service(
+                        scope("/tokens/")
+                            .service(create_token)
+                            .service(get_user_tokens)
+                            .service(revoke_token),
+                    )
+                    .service(
+                        scope("/admin/")
+                            .service(search_users)
+                            .service(suspend_user)
+                            .service(unsuspend_user)
+                            .service(deactivate_account)
+                            .service(search_routes)
+                            .service(reassign_route)
+                            .service(disable_route)
+                            .service(enable_route)
+                            .service(system_stats)
+                            .service(audit_trail),
                     )
                     .service(
                         scope("/availability/")
@@ -94,9 +235,33 @@ async fn main() -> std::io::Result<()> {
             .service(redirect_to_console)
             .data(AppState {
                 db: db_addr.clone(),
+                clicks: clicks_addr.clone(),
+                ip_salt: ip_salt.clone(),
+                slugs: slugs.clone(),
+                slug_length: slug_length.clone(),
+                targets: targets.clone(),
+                console_url: console_url.clone(),
+                mailer: mail_addr.clone(),
+                require_verified_email,
+                verification_code_ttl,
+                password_reset_ttl,
+                session_ttl: session_ttl.into(),
+                login_throttle: login_throttle.clone(),
+                totp_cipher: totp_cipher.clone(),
+                totp_issuer: totp_issuer.clone(),
+                inactive_owner_routes,
+                geoip: geoip.clone(),
             })
-    })
-    .bind(("0.0.0.0", 9600))?
-    .run()
-    .await
+    });
+    let server = match config.server.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    server.bind(&config.server.bind)?.run().await?;
+
+    // don't lose clicks still sitting in the buffer
+    if let Err(err) = click_writer.send(Flush).await {
+        error!("click writer unavailable on shutdown: {}", err);
+    }
+    Ok(())
 }
@@ -0,0 +1,186 @@
+use actix_web::cookie::{Cookie, CookieJar, Key};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, HeaderValue};
+use actix_web::Error;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use time::Duration;
+
+/// Cookie name used by `RedisSession` unless told otherwise
+pub const SESSION_COOKIE: &str = "actix-session";
+/// Shortest key `RedisSession` accepts
+pub const MIN_KEY_LENGTH: usize = 32;
+
+/// Session signing keys, `primary` signs new cookies and `old` ones are still accepted
+#[derive(Clone, Debug)]
+pub struct SessionKeys {
+    pub primary: Vec<u8>,
+    pub old: Vec<Vec<u8>>,
+}
+
+/// Lets cookies signed with an old key keep working after the primary key changes.
+///
+/// Wrap it around `RedisSession`: a session cookie that only verifies with an old
+/// key is re-signed with the primary key before the session middleware sees it,
+/// and the client is handed the re-signed cookie so it stops using the old one.
+pub struct KeyRotation(Rc<Inner>);
+
+struct Inner {
+    cookie_name: String,
+    primary: Key,
+    old: Vec<Key>,
+    max_age: Duration,
+}
+
+impl KeyRotation {
+    pub fn new(keys: &SessionKeys) -> Self {
+        KeyRotation(Rc::new(Inner {
+            cookie_name: SESSION_COOKIE.to_string(),
+            primary: Key::derive_from(&keys.primary),
+            old: keys.old.iter().map(|key| Key::derive_from(key)).collect(),
+            // same as RedisSession
+            max_age: Duration::days(7),
+        }))
+    }
+}
+
+impl<S, B> Transform<S> for KeyRotation
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = KeyRotationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(KeyRotationMiddleware {
+            service,
+            inner: self.0.clone(),
+        }))
+    }
+}
+
+pub struct KeyRotationMiddleware<S> {
+    service: S,
+    inner: Rc<Inner>,
+}
+
+impl<S, B> Service for KeyRotationMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: ServiceRequest) -> Self::Future {
+        let resigned = self.inner.resign_request(&mut req);
+        let inner = self.inner.clone();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Some(value) = resigned {
+                inner.upgrade_client(&mut res, value);
+            }
+            Ok(res)
+        })
+    }
+}
+
+impl Inner {
+    /// Re-signs a session cookie signed with an old key, returns the new signed value
+    fn resign_request(&self, req: &mut ServiceRequest) -> Option<String> {
+        if self.old.is_empty() {
+            return None;
+        }
+        let header = req
+            .headers()
+            .get(header::COOKIE)?
+            .to_str()
+            .ok()?
+            .to_string();
+
+        let mut resigned = None;
+        let pairs: Vec<String> = header
+            .split(';')
+            .map(|pair| {
+                let pair = pair.trim();
+                match Cookie::parse_encoded(pair.to_string()) {
+                    Ok(cookie) if cookie.name() == self.cookie_name => match self.resign(cookie) {
+                        Some(value) => {
+                            let pair = format!("{}={}", self.cookie_name, value);
+                            resigned = Some(value);
+                            pair
+                        }
+                        None => pair.to_string(),
+                    },
+                    _ => pair.to_string(),
+                }
+            })
+            .collect();
+
+        let value = resigned?;
+        let header_value = HeaderValue::from_str(&pairs.join("; ")).ok()?;
+        req.headers_mut().insert(header::COOKIE, header_value);
+        Some(value)
+    }
+
+    /// None when the cookie is already signed by the primary key or by no known key
+    fn resign(&self, cookie: Cookie<'static>) -> Option<String> {
+        let mut jar = CookieJar::new();
+        jar.add_original(cookie);
+        if jar.signed(&self.primary).get(&self.cookie_name).is_some() {
+            return None;
+        }
+
+        let session_id = self
+            .old
+            .iter()
+            .find_map(|key| jar.signed(key).get(&self.cookie_name))?
+            .value()
+            .to_string();
+
+        let mut jar = CookieJar::new();
+        jar.signed(&self.primary)
+            .add(Cookie::new(self.cookie_name.clone(), session_id));
+        jar.get(&self.cookie_name)
+            .map(|cookie| cookie.value().to_string())
+    }
+
+    /// Hands the re-signed cookie to the client unless the session middleware replaced it
+    fn upgrade_client<B>(&self, res: &mut ServiceResponse<B>, value: String) {
+        let already_set = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .filter_map(|value| value.to_str().ok())
+            .any(|cookie| cookie.starts_with(&format!("{}=", self.cookie_name)));
+        if already_set {
+            return;
+        }
+
+        let cookie = Cookie::build(self.cookie_name.clone(), value)
+            .path("/")
+            .http_only(true)
+            .max_age(self.max_age)
+            .finish();
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            res.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Default)]
+/// Counts for the admin dashboard
+pub struct SystemStats {
+    pub users: i64,
+    /// users whose account is active
+    pub active_users: i64,
+    pub suspended_users: i64,
+    pub admins: i64,
+    pub routes: i64,
+    /// routes with the active flag set, regardless of their window
+    pub active_routes: i64,
+    /// redirects served over all routes
+    pub visits: i64,
+    pub clicks_last_24h: i64,
+    /// logged in sessions seen within the last day
+    pub sessions_last_24h: i64,
+}
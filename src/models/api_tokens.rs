@@ -0,0 +1,93 @@
+use crate::schema::api_tokens;
+use diesel::{Insertable, Queryable};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use chrono::NaiveDateTime;
+
+pub const SCOPE_ROUTES_READ: &str = "routes:read";
+pub const SCOPE_ROUTES_WRITE: &str = "routes:write";
+pub const SCOPE_STATS_READ: &str = "stats:read";
+pub const SCOPE_USER_READ: &str = "user:read";
+/// Every scope a token can be granted
+pub const SCOPES: &[&str] = &[
+    SCOPE_ROUTES_READ,
+    SCOPE_ROUTES_WRITE,
+    SCOPE_STATS_READ,
+    SCOPE_USER_READ,
+];
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+/// To get data from DB
+pub struct ApiToken {
+    /// Unique identifier, used to revoke the token
+    pub id: Uuid,
+    /// Owner of the token, requests made with it act as this user
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    /// Label chosen by the user e.g. "CI"
+    pub name: String,
+    /// sha256 of the token, only ever compared in SQL
+    #[serde(skip_serializing)]
+    #[allow(dead_code)]
+    pub token_hash: String,
+    /// What the token may be used for
+    pub scopes: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+    /// Token is refused from this time (UTC) on
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "api_tokens"]
+/// To insert data in DB
+pub struct NewApiToken {
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+/// To receive data from HTTP request
+pub struct ApiTokenData {
+    /// Label to recognise the token by
+    #[validate(length(min = 1, max = 100, message = "name must be 1 to 100 characters"))]
+    pub name: String,
+    /// Subset of SCOPES
+    #[validate(custom = "validate_scopes")]
+    pub scopes: Vec<String>,
+    /// Optional expiry time (UTC)
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+fn validate_scopes(scopes: &[String]) -> Result<(), ValidationError> {
+    if scopes.is_empty() {
+        let mut error = ValidationError::new("no_scopes");
+        error.message = Some("a token needs at least one scope".into());
+        return Err(error);
+    }
+    match scopes
+        .iter()
+        .find(|scope| !SCOPES.contains(&scope.as_str()))
+    {
+        Some(unknown) => {
+            let mut error = ValidationError::new("unknown_scope");
+            error.message = Some(format!("unknown scope \"{}\"", unknown).into());
+            error.add_param("allowed".into(), &SCOPES);
+            Err(error)
+        }
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// Returned once when a token is created, the plain token can't be retrieved again
+pub struct CreatedApiToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub details: ApiToken,
+}
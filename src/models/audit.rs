@@ -0,0 +1,147 @@
+use crate::schema::audit_events;
+use diesel::{Insertable, Queryable};
+use serde::Serialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use chrono::NaiveDateTime;
+
+pub const TARGET_USER: &str = "user";
+pub const TARGET_ROUTE: &str = "route";
+pub const TARGET_API_TOKEN: &str = "api_token";
+
+pub const ACTION_USER_REGISTER: &str = "user.register";
+pub const ACTION_USER_LOGIN: &str = "user.login";
+pub const ACTION_USER_LOGIN_FAILED: &str = "user.login_failed";
+pub const ACTION_USER_LOGOUT: &str = "user.logout";
+pub const ACTION_USER_UPDATE: &str = "user.update";
+pub const ACTION_USER_DELETE: &str = "user.delete";
+pub const ACTION_USER_DEACTIVATE: &str = "user.deactivate";
+pub const ACTION_USER_REACTIVATE: &str = "user.reactivate";
+pub const ACTION_USER_VERIFY_EMAIL: &str = "user.verify_email";
+pub const ACTION_USER_PASSWORD_RESET: &str = "user.password_reset";
+pub const ACTION_USER_2FA_ENABLE: &str = "user.2fa_enable";
+pub const ACTION_USER_2FA_DISABLE: &str = "user.2fa_disable";
+pub const ACTION_USER_TOKEN_CREATE: &str = "user.token_create";
+pub const ACTION_USER_TOKEN_REVOKE: &str = "user.token_revoke";
+
+pub const ACTION_ROUTE_CREATE: &str = "route.create";
+pub const ACTION_ROUTE_UPDATE: &str = "route.update";
+pub const ACTION_ROUTE_DELETE: &str = "route.delete";
+pub const ACTION_ROUTE_ROLLBACK: &str = "route.rollback";
+pub const ACTION_ROUTE_SCHEDULE_CREATE: &str = "route.schedule_create";
+pub const ACTION_ROUTE_SCHEDULE_DELETE: &str = "route.schedule_delete";
+pub const ACTION_ROUTE_VARIANT_CREATE: &str = "route.variant_create";
+pub const ACTION_ROUTE_VARIANT_UPDATE: &str = "route.variant_update";
+pub const ACTION_ROUTE_VARIANT_DELETE: &str = "route.variant_delete";
+/// done by the background job, so without actor
+pub const ACTION_ROUTE_SCHEDULE_APPLY: &str = "route.schedule_apply";
+
+pub const ACTION_ADMIN_USER_SUSPEND: &str = "admin.user.suspend";
+pub const ACTION_ADMIN_USER_UNSUSPEND: &str = "admin.user.unsuspend";
+pub const ACTION_ADMIN_USER_DEACTIVATE: &str = "admin.user.deactivate";
+pub const ACTION_ADMIN_ROUTE_REASSIGN: &str = "admin.route.reassign";
+pub const ACTION_ADMIN_ROUTE_DISABLE: &str = "admin.route.disable";
+pub const ACTION_ADMIN_ROUTE_ENABLE: &str = "admin.route.enable";
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+/// To get data from DB
+pub struct AuditEvent {
+    pub id: i64,
+    /// who did it, None once their account is deleted or for anonymous requests
+    pub actor_id: Option<Uuid>,
+    /// what was done, one of the ACTION_ constants
+    pub action: String,
+    /// one of the TARGET_ constants
+    pub target_type: String,
+    pub target_id: Option<Uuid>,
+    /// action specific, e.g. the suspension reason
+    pub details: Value,
+    pub created_at: NaiveDateTime,
+    /// whose history the event shows up in
+    #[serde(skip_serializing)]
+    #[allow(dead_code)]
+    pub user_id: Option<Uuid>,
+    /// the target as it was before, None when it did not exist
+    pub before: Option<Value>,
+    /// the target as it is after, None when it is gone
+    pub after: Option<Value>,
+    /// client IP of the request
+    pub ip: Option<String>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "audit_events"]
+/// To insert data in DB, start with `new` and add what is known
+pub struct NewAuditEvent {
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<Uuid>,
+    pub details: Value,
+    pub user_id: Option<Uuid>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub ip: Option<String>,
+}
+
+/// Serialized form of a target, values that fail to serialize are recorded as null
+fn snapshot<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+impl NewAuditEvent {
+    pub fn new(action: &str, target_type: &str, target_id: Uuid) -> Self {
+        NewAuditEvent {
+            actor_id: None,
+            action: action.to_string(),
+            target_type: target_type.to_string(),
+            target_id: Some(target_id),
+            details: json!({}),
+            user_id: None,
+            before: None,
+            after: None,
+            ip: None,
+        }
+    }
+
+    /// Done by `actor` to their own account or routes
+    pub fn by(mut self, actor: Uuid) -> Self {
+        self.actor_id = Some(actor);
+        self.user_id = Some(actor);
+        self
+    }
+
+    /// Done by `actor` to what belongs to `user`, e.g. by an admin
+    pub fn by_for(mut self, actor: Uuid, user: Option<Uuid>) -> Self {
+        self.actor_id = Some(actor);
+        self.user_id = user;
+        self
+    }
+
+    /// Concerns the account of `user` but nobody is logged in, e.g. a failed login
+    pub fn concerning(mut self, user: Uuid) -> Self {
+        self.user_id = Some(user);
+        self
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn before<T: Serialize>(mut self, value: &T) -> Self {
+        self.before = Some(snapshot(value));
+        self
+    }
+
+    pub fn after<T: Serialize>(mut self, value: &T) -> Self {
+        self.after = Some(snapshot(value));
+        self
+    }
+
+    pub fn ip(mut self, ip: Option<String>) -> Self {
+        self.ip = ip;
+        self
+    }
+}
@@ -0,0 +1,100 @@
+use crate::models::route_targets::VariantCount;
+use crate::schema::clicks;
+use diesel::sql_types::{BigInt, Nullable, Text, Timestamp};
+use diesel::{Insertable, QueryableByName};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "clicks"]
+/// To insert data in DB
+pub struct NewClick {
+    /// Route which was visited
+    pub route_id: Uuid,
+    /// Time (UTC) of the redirect
+    pub clicked_at: NaiveDateTime,
+    /// Referer header sent by the client
+    pub referrer: Option<String>,
+    /// User-Agent header sent by the client
+    pub user_agent: Option<String>,
+    /// Salted hash of the client IP
+    pub ip_hash: Option<String>,
+    /// Browser family parsed from the User-Agent
+    pub browser: Option<String>,
+    /// OS family parsed from the User-Agent
+    pub os: Option<String>,
+    /// ISO 3166-1 alpha-2 country of the client
+    pub country: Option<String>,
+    /// Variant of an A/B split the client was sent to
+    pub variant_id: Option<Uuid>,
+}
+
+/// Granularity of the click time series
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsBucket {
+    Hour,
+    Day,
+    Month,
+}
+
+impl StatsBucket {
+    /// Field name understood by postgres' date_trunc
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StatsBucket::Hour => "hour",
+            StatsBucket::Day => "day",
+            StatsBucket::Month => "month",
+        }
+    }
+
+    /// Rough number of buckets between two times, used to cap the series length
+    pub fn count(self, from: NaiveDateTime, to: NaiveDateTime) -> i64 {
+        let hours = (to - from).num_hours();
+        match self {
+            StatsBucket::Hour => hours,
+            StatsBucket::Day => hours / 24,
+            StatsBucket::Month => hours / (24 * 28),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+/// Number of clicks starting at `bucket`
+pub struct ClickBucket {
+    #[sql_type = "Timestamp"]
+    pub bucket: NaiveDateTime,
+    #[sql_type = "BigInt"]
+    pub clicks: i64,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+/// Number of clicks sharing a value, `name` is null when it was unknown
+pub struct ClickCount {
+    #[sql_type = "Nullable<Text>"]
+    pub name: Option<String>,
+    #[sql_type = "BigInt"]
+    pub clicks: i64,
+}
+
+#[derive(Debug, Serialize)]
+/// Click analytics of a single route over a time range
+pub struct RouteStats {
+    pub route_id: Uuid,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub bucket: StatsBucket,
+    /// Clicks in the whole range
+    pub total: i64,
+    /// Clicks per bucket, empty buckets included
+    pub series: Vec<ClickBucket>,
+    /// Referring hosts, null counts direct visits
+    pub referrers: Vec<ClickCount>,
+    pub browsers: Vec<ClickCount>,
+    pub operating_systems: Vec<ClickCount>,
+    pub countries: Vec<ClickCount>,
+    /// Clicks per A/B variant, empty for routes without variants
+    pub variants: Vec<VariantCount>,
+}
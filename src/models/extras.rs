@@ -1,6 +1,39 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use validator::ValidationErrors;
 
 #[derive(Serialize, Debug)]
 pub struct AppError {
     pub error: String,
+    /// validation errors keyed by field name, same shape the validator crate produces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<ValidationErrors>,
+}
+
+impl AppError {
+    pub fn new<S: Into<String>>(error: S) -> Self {
+        AppError {
+            error: error.into(),
+            fields: None,
+        }
+    }
+}
+
+/// Largest page a listing returns
+pub const MAX_PER_PAGE: i64 = 100;
+
+#[derive(Deserialize, Debug)]
+/// `?page=&per_page=` of paged listings, pages start at 1
+pub struct Pagination {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl Pagination {
+    pub fn limit(&self) -> i64 {
+        self.per_page.unwrap_or(20).clamp(1, MAX_PER_PAGE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page.unwrap_or(1).max(1) - 1).saturating_mul(self.limit())
+    }
 }
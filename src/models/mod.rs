@@ -1,10 +1,64 @@
 use crate::actix::Addr;
+use crate::actors::click_writer::ClickWriter;
 use crate::actors::db::DbActor;
+use crate::actors::mailer::MailActor;
+use crate::models::routes::InactiveOwnerPolicy;
+use crate::utils::geoip::GeoIp;
+use crate::utils::login_throttle::LoginThrottle;
+use crate::utils::slug::SlugGenerator;
+use crate::utils::target::TargetPolicy;
+use crate::utils::totp::SecretCipher;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
 
 pub struct AppState {
     pub db: Addr<DbActor>,
+    /// buffers clicks and writes them to `db` in batches
+    pub clicks: Addr<ClickWriter>,
+    /// salt mixed into client IPs before they are hashed for click tracking
+    pub ip_salt: String,
+    /// generates slugs for routes created without one
+    pub slugs: SlugGenerator,
+    /// length generated slugs start at, shared by all workers and grown once
+    /// collisions pile up so later requests don't pay for them again
+    pub slug_length: Arc<AtomicUsize>,
+    /// validates and normalizes route targets
+    pub targets: TargetPolicy,
+    /// where requests for the bare domain are sent
+    pub console_url: String,
+    /// delivers mail without blocking request handlers
+    pub mailer: Addr<MailActor>,
+    /// routes may only be created once the owner's email is verified
+    pub require_verified_email: bool,
+    /// minutes an email verification code is valid
+    pub verification_code_ttl: i64,
+    /// minutes a password reset link is valid
+    pub password_reset_ttl: i64,
+    /// seconds a session lives after it was last written
+    pub session_ttl: i64,
+    /// locks out usernames and IPs after repeated failed logins
+    pub login_throttle: LoginThrottle,
+    /// encrypts TOTP secrets, None when 2FA is not configured
+    pub totp_cipher: Option<SecretCipher>,
+    /// name shown in authenticator apps
+    pub totp_issuer: String,
+    /// what routes of deactivated or suspended accounts do
+    pub inactive_owner_routes: InactiveOwnerPolicy,
+    /// locates clients for geo rules and click stats, None without a database
+    pub geoip: Option<Arc<GeoIp>>,
 }
 
+pub mod admin;
+pub mod api_tokens;
+pub mod audit;
+pub mod clicks;
 pub mod extras;
+pub mod password_resets;
+pub mod route_rules;
+pub mod route_schedules;
+pub mod route_targets;
+pub mod route_versions;
 pub mod routes;
+pub mod user_sessions;
 pub mod users;
+pub mod verifications;
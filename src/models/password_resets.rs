@@ -0,0 +1,28 @@
+use crate::schema::password_resets;
+use diesel::{Insertable, Queryable};
+use uuid::Uuid;
+
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Queryable)]
+/// To get data from DB, most columns are only ever compared in SQL
+#[allow(dead_code)]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// sha256 of the token mailed to the user
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    /// set once the token was used, tokens work only once
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "password_resets"]
+/// To insert data in DB
+pub struct NewPasswordReset {
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
@@ -0,0 +1,272 @@
+use crate::utils::target::TargetPolicy;
+use crate::utils::user_agent::{DeviceClass, OsFamily};
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Jsonb;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use validator::ValidationError;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Rules a single route may carry
+pub const MAX_RULES: usize = 20;
+/// Longest browser name a rule may match on
+const MAX_BROWSER_LENGTH: usize = 64;
+/// Languages a single language rule may offer
+const MAX_LANGUAGES: usize = 50;
+/// Longest language tag a rule may offer, as in RFC 5646
+const MAX_LANGUAGE_LENGTH: usize = 35;
+/// Continent codes as found in GeoIP databases
+const CONTINENTS: &[&str] = &["AF", "AN", "AS", "EU", "NA", "OC", "SA"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[sql_type = "Jsonb"]
+#[serde(transparent)]
+/// Targeting rules of a route in the order they are tried, stored as JSON
+pub struct RouteRules(pub Vec<RouteRule>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+/// One rule, sends matching requests to its own target
+pub enum RouteRule {
+    /// matches on the parsed User-Agent
+    Device(DeviceRule),
+    /// matches on where the client IP is located
+    Geo(GeoRule),
+    /// picks a target by the client's Accept-Language
+    Language(LanguageRule),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Every condition given must hold, an empty list accepts anything
+pub struct DeviceRule {
+    /// e.g. ["ios"] for the App Store link
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub os: Vec<OsFamily>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device: Vec<DeviceClass>,
+    /// browser families as reported in click stats, e.g. "Chrome", case-insensitive
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub browser: Vec<String>,
+    /// true for crawlers only, false for everyone else
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bot: Option<bool>,
+    /// Target where matching requestees are redirected
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Matches when the country or the continent is listed
+pub struct GeoRule {
+    /// ISO 3166-1 alpha-2 codes e.g. ["DE", "AT", "CH"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub country: Vec<String>,
+    /// AF, AN, AS, EU, NA, OC or SA
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub continent: Vec<String>,
+    /// Target where matching requestees are redirected
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Matches when the client accepts one of the languages and sends it to the best
+/// of them, see `accept_language::best_match`
+pub struct LanguageRule {
+    /// language tag to target, e.g. {"de": "https://example.com/de/docs"}
+    pub languages: BTreeMap<String, String>,
+}
+
+impl RouteRule {
+    fn targets_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            RouteRule::Device(rule) => vec![&mut rule.target],
+            RouteRule::Geo(rule) => vec![&mut rule.target],
+            RouteRule::Language(rule) => rule.languages.values_mut().collect(),
+        }
+    }
+
+    /// Whatever makes the rule unusable besides its targets, normalizes the case of
+    /// codes and language tags
+    fn check(&mut self) -> Result<(), String> {
+        match self {
+            RouteRule::Device(rule) => {
+                if rule.os.is_empty()
+                    && rule.device.is_empty()
+                    && rule.browser.is_empty()
+                    && rule.bot.is_none()
+                {
+                    return Err("needs at least one of os, device, browser or bot".to_string());
+                }
+                if rule
+                    .browser
+                    .iter()
+                    .any(|browser| browser.trim().is_empty() || browser.len() > MAX_BROWSER_LENGTH)
+                {
+                    return Err(format!(
+                        "browser names must have 1 to {} characters",
+                        MAX_BROWSER_LENGTH
+                    ));
+                }
+                Ok(())
+            }
+            RouteRule::Geo(rule) => {
+                if rule.country.is_empty() && rule.continent.is_empty() {
+                    return Err("needs at least one country or continent".to_string());
+                }
+                for country in rule.country.iter_mut() {
+                    *country = country.trim().to_uppercase();
+                    if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                        return Err(format!("\"{}\" is not a two letter country code", country));
+                    }
+                }
+                for continent in rule.continent.iter_mut() {
+                    *continent = continent.trim().to_uppercase();
+                    if !CONTINENTS.contains(&continent.as_str()) {
+                        return Err(format!(
+                            "continent must be one of {}, got \"{}\"",
+                            CONTINENTS.join(", "),
+                            continent
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            RouteRule::Language(rule) => {
+                if rule.languages.is_empty() || rule.languages.len() > MAX_LANGUAGES {
+                    return Err(format!("needs 1 to {} languages", MAX_LANGUAGES));
+                }
+                let mut languages = BTreeMap::new();
+                for (language, target) in std::mem::take(&mut rule.languages) {
+                    let language = language.trim().to_lowercase();
+                    if !is_language_tag(&language) {
+                        return Err(format!("\"{}\" is not a language tag", language));
+                    }
+                    if languages.insert(language.clone(), target).is_some() {
+                        return Err(format!("\"{}\" is given twice", language));
+                    }
+                }
+                rule.languages = languages;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Loosely RFC 5646: subtags of 1 to 8 letters or digits, the first one letters
+fn is_language_tag(tag: &str) -> bool {
+    tag.len() <= MAX_LANGUAGE_LENGTH
+        && tag.split('-').enumerate().all(|(index, subtag)| {
+            !subtag.is_empty()
+                && subtag.len() <= 8
+                && subtag.chars().all(|c| {
+                    if index == 0 {
+                        c.is_ascii_alphabetic()
+                    } else {
+                        c.is_ascii_alphanumeric()
+                    }
+                })
+        })
+}
+
+impl RouteRules {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Checks every rule and normalizes their targets like the route's own target,
+    /// the error names the first bad rule counting from 1
+    pub fn normalized(&self, targets: &TargetPolicy) -> Result<RouteRules, ValidationError> {
+        if self.0.len() > MAX_RULES {
+            let mut error = ValidationError::new("too_many_rules");
+            error.message = Some(format!("a route can have at most {} rules", MAX_RULES).into());
+            error.add_param(Cow::from("max"), &MAX_RULES);
+            return Err(error);
+        }
+
+        let mut rules = self.clone();
+        for (index, rule) in rules.0.iter_mut().enumerate() {
+            let problem = rule.check().and_then(|()| {
+                for target in rule.targets_mut() {
+                    *target = targets.normalize(target).map_err(|error| {
+                        error
+                            .message
+                            .map(|message| message.to_string())
+                            .unwrap_or_else(|| "target is invalid".to_string())
+                    })?;
+                }
+                Ok(())
+            });
+            if let Err(problem) = problem {
+                let mut error = ValidationError::new("invalid_rule");
+                error.message = Some(format!("rule {}: {}", index + 1, problem).into());
+                error.add_param(Cow::from("rule"), &(index + 1));
+                return Err(error);
+            }
+        }
+        Ok(rules)
+    }
+}
+
+impl FromSql<Jsonb, Pg> for RouteRules {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <Value as FromSql<Jsonb, Pg>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl ToSql<Jsonb, Pg> for RouteRules {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        let value = serde_json::to_value(self)?;
+        <Value as ToSql<Jsonb, Pg>>::to_sql(&value, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geo(country: &[&str], continent: &[&str]) -> RouteRule {
+        RouteRule::Geo(GeoRule {
+            country: country.iter().map(|code| code.to_string()).collect(),
+            continent: continent.iter().map(|code| code.to_string()).collect(),
+            target: "https://example.com".to_string(),
+        })
+    }
+
+    #[test]
+    fn geo_codes_are_normalized() {
+        let mut rule = geo(&["de", " at "], &["eu"]);
+        assert!(rule.check().is_ok());
+        match rule {
+            RouteRule::Geo(rule) => {
+                assert_eq!(rule.country, vec!["DE", "AT"]);
+                assert_eq!(rule.continent, vec!["EU"]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn geo_needs_a_code() {
+        assert!(geo(&[], &[]).check().is_err());
+        assert!(geo(&["DE"], &[]).check().is_ok());
+        assert!(geo(&[], &["EU"]).check().is_ok());
+    }
+
+    #[test]
+    fn bad_geo_codes_are_rejected() {
+        let error = geo(&[], &["XX"]).check().unwrap_err();
+        assert!(error.contains("\"XX\""), "{}", error);
+        assert!(geo(&[], &["Europe"]).check().is_err());
+        assert!(geo(&["DEU"], &[]).check().is_err());
+        assert!(geo(&["D1"], &[]).check().is_err());
+        assert!(geo(&[""], &[]).check().is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use crate::schema::route_schedules;
+use diesel::{Insertable, Queryable};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+/// To get data from DB
+pub struct RouteSchedule {
+    pub id: Uuid,
+    pub route_id: Uuid,
+    /// when (UTC) `target` takes over
+    pub effective_at: NaiveDateTime,
+    pub target: String,
+    /// who scheduled it, None once their account is deleted
+    pub created_by: Option<Uuid>,
+    /// when the background job wrote `target` to the route, None while pending
+    pub applied_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    /// when the target was changed by hand, or a later schedule of the route came
+    /// due, before this one was applied. Superseded schedules are never applied
+    pub superseded_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "route_schedules"]
+/// To insert data in DB
+pub struct NewRouteSchedule {
+    pub route_id: Uuid,
+    pub effective_at: NaiveDateTime,
+    pub target: String,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+/// To receive data from HTTP request
+pub struct RouteScheduleData {
+    /// Time (UTC) from which `target` is used, must be in the future
+    pub effective_at: NaiveDateTime,
+    /// Target where requestee should be redirected from then on
+    #[validate(length(min = 1, message = "target must not be empty"))]
+    pub target: String,
+}
@@ -0,0 +1,117 @@
+use crate::schema::route_targets;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{BigInt, Jsonb, Nullable, Text, Uuid as SqlUuid};
+use diesel::{Insertable, Queryable, QueryableByName};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use chrono::NaiveDateTime;
+use std::io::Write;
+
+/// Variants a single route may split traffic across
+pub const MAX_VARIANTS: usize = 20;
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+/// To get data from DB, one variant of an A/B split
+pub struct RouteTarget {
+    pub id: Uuid,
+    pub route_id: Uuid,
+    pub target: String,
+    /// share of traffic relative to the other variants, 0 pauses it
+    pub weight: i32,
+    /// name to tell variants apart in stats, e.g. "B"
+    pub label: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A variant as a route version keeps it
+pub struct VariantSnapshot {
+    pub id: Uuid,
+    pub target: String,
+    pub weight: i32,
+    pub label: Option<String>,
+}
+
+impl From<&RouteTarget> for VariantSnapshot {
+    fn from(variant: &RouteTarget) -> Self {
+        VariantSnapshot {
+            id: variant.id,
+            target: variant.target.clone(),
+            weight: variant.weight,
+            label: variant.label.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[sql_type = "Jsonb"]
+#[serde(transparent)]
+/// Variants of a route in one of its versions, stored as JSON
+pub struct VariantSnapshots(pub Vec<VariantSnapshot>);
+
+impl FromSql<Jsonb, Pg> for VariantSnapshots {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <Value as FromSql<Jsonb, Pg>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl ToSql<Jsonb, Pg> for VariantSnapshots {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        let value = serde_json::to_value(self)?;
+        <Value as ToSql<Jsonb, Pg>>::to_sql(&value, out)
+    }
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "route_targets"]
+/// To insert data in DB
+pub struct NewRouteTarget {
+    pub route_id: Uuid,
+    pub target: String,
+    pub weight: i32,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+/// To receive a new variant from HTTP request
+pub struct RouteTargetData {
+    /// Target where requestee should be redirected
+    #[validate(length(min = 1, message = "target must not be empty"))]
+    pub target: String,
+    #[validate(range(min = 0, max = 10000, message = "weight must be between 0 and 10000"))]
+    pub weight: i32,
+    #[validate(length(max = 64, message = "label must be at most 64 characters"))]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+/// To receive changes to a variant from HTTP request, the target can't change
+/// so its clicks keep meaning the same thing
+pub struct UpdateRouteTargetData {
+    #[validate(range(min = 0, max = 10000, message = "weight must be between 0 and 10000"))]
+    pub weight: i32,
+    #[validate(length(max = 64, message = "label must be at most 64 characters"))]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+/// Clicks one variant of a route received
+pub struct VariantCount {
+    #[sql_type = "SqlUuid"]
+    pub variant_id: Uuid,
+    #[sql_type = "Nullable<Text>"]
+    pub label: Option<String>,
+    #[sql_type = "Text"]
+    pub target: String,
+    #[sql_type = "BigInt"]
+    pub clicks: i64,
+    /// distinct hashed IPs
+    #[sql_type = "BigInt"]
+    pub visitors: i64,
+}
@@ -0,0 +1,76 @@
+use crate::models::route_rules::RouteRules;
+use crate::models::route_targets::{RouteTarget, VariantSnapshot, VariantSnapshots};
+use crate::models::routes::Route;
+use crate::schema::route_versions;
+use diesel::{Insertable, Queryable};
+use serde::Serialize;
+use uuid::Uuid;
+
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+/// To get data from DB, a route as it was after one change
+pub struct RouteVersion {
+    #[serde(skip_serializing)]
+    #[allow(dead_code)]
+    pub id: i64,
+    pub route_id: Uuid,
+    /// 1 for the route as created, counts up with every change
+    pub version: i32,
+    pub slug: String,
+    pub target: String,
+    pub active: bool,
+    pub active_from: Option<NaiveDateTime>,
+    pub active_till: Option<NaiveDateTime>,
+    /// who made the change, None once their account is deleted
+    pub changed_by: Option<Uuid>,
+    /// the version this one restored, for rollbacks
+    pub rolled_back_from: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub rules: RouteRules,
+    /// None for versions recorded before variants were kept
+    pub variants: Option<VariantSnapshots>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "route_versions"]
+/// To insert data in DB
+pub struct NewRouteVersion {
+    pub route_id: Uuid,
+    pub version: i32,
+    pub slug: String,
+    pub target: String,
+    pub active: bool,
+    pub active_from: Option<NaiveDateTime>,
+    pub active_till: Option<NaiveDateTime>,
+    pub changed_by: Option<Uuid>,
+    pub rolled_back_from: Option<i32>,
+    pub rules: RouteRules,
+    pub variants: Option<VariantSnapshots>,
+}
+
+impl NewRouteVersion {
+    /// Snapshot of `route` and its `variants` as they are now
+    pub fn of(
+        route: &Route,
+        variants: &[RouteTarget],
+        version: i32,
+        changed_by: Option<Uuid>,
+    ) -> Self {
+        NewRouteVersion {
+            route_id: route.id,
+            version,
+            slug: route.slug.clone(),
+            target: route.target.clone(),
+            active: route.active,
+            active_from: route.active_from,
+            active_till: route.active_till,
+            changed_by,
+            rolled_back_from: None,
+            rules: route.rules.clone(),
+            variants: Some(VariantSnapshots(
+                variants.iter().map(VariantSnapshot::from).collect(),
+            )),
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use crate::models::route_rules::RouteRules;
+use crate::models::route_targets::RouteTarget;
 use crate::schema::routes;
 use diesel::{Insertable, Queryable};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::{Validate, ValidationError};
 
 use chrono::NaiveDateTime;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Queryable, Serialize, Deserialize)]
 /// To get data from DB
@@ -27,6 +31,62 @@ pub struct Route {
     pub created_at: NaiveDateTime,
     #[serde(skip_serializing)]
     pub updated_at: NaiveDateTime,
+    /// Number of redirects served
+    pub visits: i64,
+    /// Number of distinct visitors (by hashed IP) redirected
+    pub unique_visits: i64,
+    /// Tried in order before the target, the first match decides where to redirect
+    pub rules: RouteRules,
+}
+
+/// What redirect_by_slug does for routes whose owner is deactivated or suspended
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InactiveOwnerPolicy {
+    /// keep redirecting as if nothing happened
+    Redirect,
+    /// show a warning page with a link to the target instead of redirecting, not counted
+    /// as a click
+    Interstitial,
+    /// answer 410 Gone
+    Gone,
+}
+
+impl FromStr for InactiveOwnerPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "redirect" => Ok(InactiveOwnerPolicy::Redirect),
+            "interstitial" => Ok(InactiveOwnerPolicy::Interstitial),
+            "gone" => Ok(InactiveOwnerPolicy::Gone),
+            other => Err(format!(
+                "expected \"redirect\", \"interstitial\" or \"gone\", got \"{}\"",
+                other
+            )),
+        }
+    }
+}
+
+/// Where a route stands relative to its active_from/active_till window
+pub enum RouteWindow {
+    /// route has not reached active_from yet
+    Pending(NaiveDateTime),
+    /// route is inside its window (or has none)
+    Live,
+    /// route is past active_till
+    Expired,
+}
+
+impl Route {
+    /// Checks the scheduling window against `now` (UTC), ignores the `active` flag
+    pub fn window(&self, now: NaiveDateTime) -> RouteWindow {
+        match (self.active_from, self.active_till) {
+            (Some(from), _) if now < from => RouteWindow::Pending(from),
+            (_, Some(till)) if now >= till => RouteWindow::Expired,
+            _ => RouteWindow::Live,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
@@ -41,15 +101,91 @@ pub struct NewRoute {
     pub target: String,
     /// Is the link active
     pub active: Option<bool>,
+    /// Time (UTC) from which the link will be active
+    pub active_from: Option<NaiveDateTime>,
+    /// Time (UTC) till which the link should be active
+    pub active_till: Option<NaiveDateTime>,
+    /// Tried in order before the target
+    pub rules: RouteRules,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_route_data_window"))]
 /// To receive data from HTTP request thus Uuid not necessary
 pub struct RouteData {
-    /// slug part of elide URL, elide.com/this-is-slug
-    pub slug: String,
+    /// slug part of elide URL, elide.com/this-is-slug, generated when missing
+    #[validate(length(min = 1, message = "slug must not be empty"))]
+    pub slug: Option<String>,
     /// Target where requestee should be redirected
     pub target: String,
     /// Is the link active
     pub active: Option<bool>,
+    /// Time (UTC) from which the link will be active
+    pub active_from: Option<NaiveDateTime>,
+    /// Time (UTC) till which the link should be active
+    pub active_till: Option<NaiveDateTime>,
+    /// Tried in order before the target, none when missing
+    #[serde(default)]
+    pub rules: RouteRules,
+}
+
+/// active_from must come strictly before active_till when both are given
+pub fn validate_window(
+    active_from: Option<NaiveDateTime>,
+    active_till: Option<NaiveDateTime>,
+) -> Result<(), ValidationError> {
+    match (active_from, active_till) {
+        (Some(from), Some(till)) if from >= till => {
+            let mut error = ValidationError::new("active_window");
+            error.message = Some("active_from must be before active_till".into());
+            Err(error)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_route_data_window(route: &RouteData) -> Result<(), ValidationError> {
+    validate_window(route.active_from, route.active_till)
+}
+
+#[derive(Debug, Serialize)]
+/// A route as admins see it, with its owner
+pub struct AdminRouteInfo {
+    #[serde(flatten)]
+    pub route: Route,
+    pub creator_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl From<Route> for AdminRouteInfo {
+    fn from(route: Route) -> Self {
+        AdminRouteInfo {
+            creator_id: route.creator_id,
+            created_at: route.created_at,
+            updated_at: route.updated_at,
+            route,
+        }
+    }
+}
+
+/// A route with what redirect_by_slug needs to know besides the row itself
+#[derive(Debug, Clone)]
+pub struct RouteLookup {
+    pub route: Route,
+    /// whether the owner's account is active, None for routes without owner
+    pub owner_active: Option<bool>,
+    /// target of the latest due schedule that is not applied to the route yet
+    pub scheduled_target: Option<String>,
+    /// A/B variants with a weight above 0, oldest first
+    pub variants: Vec<RouteTarget>,
+}
+
+impl RouteLookup {
+    /// Target in effect at the time the route was looked up
+    pub fn target(&self) -> &str {
+        self.scheduled_target
+            .as_deref()
+            .unwrap_or(&self.route.target)
+    }
 }
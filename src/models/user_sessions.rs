@@ -0,0 +1,48 @@
+use crate::schema::user_sessions;
+use diesel::{Insertable, Queryable};
+use serde::Serialize;
+use uuid::Uuid;
+
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+/// To get data from DB
+pub struct UserSession {
+    /// Unique identifier, used to revoke the session
+    pub id: Uuid,
+    /// Owner, only ever compared in SQL
+    #[serde(skip_serializing)]
+    #[allow(dead_code)]
+    pub user_id: Uuid,
+    /// User-Agent header at login
+    pub user_agent: Option<String>,
+    /// browser family parsed from `user_agent`
+    pub browser: Option<String>,
+    /// OS family parsed from `user_agent`
+    pub os: Option<String>,
+    /// client IP at login
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// updated at most once a minute
+    pub last_seen_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "user_sessions"]
+/// To insert data in DB
+pub struct NewUserSession {
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+/// A session as listed to its owner
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub session: UserSession,
+    /// whether this is the session making the request
+    pub current: bool,
+}
@@ -5,6 +5,9 @@ use uuid::Uuid;
 
 use chrono::NaiveDateTime;
 
+/// May use the /api/admin/ endpoints
+pub const ROLE_ADMIN: &str = "admin";
+
 #[derive(Debug, Clone, Queryable, Serialize, Deserialize)]
 /// To get data from DB
 pub struct User {
@@ -27,6 +30,36 @@ pub struct User {
     pub created_at: NaiveDateTime,
     #[serde(skip_serializing)]
     pub updated_at: NaiveDateTime,
+    /// bumped to revoke every session of the user
+    #[serde(skip_serializing)]
+    pub session_epoch: i32,
+    /// TOTP secret sealed with `SecretCipher`, present from enrollment on
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<Vec<u8>>,
+    /// whether login asks for a TOTP code
+    pub totp_enabled: bool,
+    /// last time step a code was accepted for
+    #[serde(skip_serializing)]
+    pub totp_last_step: i64,
+    /// sha256 of the unused recovery codes
+    #[serde(skip_serializing)]
+    pub recovery_codes: Vec<String>,
+    /// when an admin suspended the account, None for accounts deactivated by their owner
+    pub suspended_at: Option<NaiveDateTime>,
+    /// shown to the user when they try to log in
+    pub suspension_reason: Option<String>,
+    /// "user" or ROLE_ADMIN
+    pub role: String,
+}
+
+impl User {
+    pub fn is_admin(&self) -> bool {
+        self.role == ROLE_ADMIN
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_at.is_some()
+    }
 }
 
 #[derive(Debug, Clone, Insertable, Deserialize)]
@@ -42,3 +75,47 @@ pub struct NewUser {
     /// email id of user
     pub email: String,
 }
+
+#[derive(Debug, Serialize)]
+/// A user as admins see them, with the fields hidden from everyone else
+pub struct AdminUserInfo {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub user: User,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<User> for AdminUserInfo {
+    fn from(user: User) -> Self {
+        AdminUserInfo {
+            id: user.id,
+            created_at: user.created_at,
+            user,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// A user as the audit trail keeps them. The table is append-only, so the name and
+/// email stay out of it and don't outlive the account
+pub struct AuditUserInfo {
+    pub id: Uuid,
+    pub email_verified: bool,
+    pub active: bool,
+    pub totp_enabled: bool,
+    pub suspended_at: Option<NaiveDateTime>,
+    pub role: String,
+}
+
+impl From<&User> for AuditUserInfo {
+    fn from(user: &User) -> Self {
+        AuditUserInfo {
+            id: user.id,
+            email_verified: user.email_verified,
+            active: user.active,
+            totp_enabled: user.totp_enabled,
+            suspended_at: user.suspended_at,
+            role: user.role.clone(),
+        }
+    }
+}
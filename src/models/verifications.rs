@@ -0,0 +1,46 @@
+use crate::schema::email_verifications;
+use diesel::{Insertable, Queryable};
+use uuid::Uuid;
+
+use chrono::NaiveDateTime;
+
+/// Wrong guesses allowed before a code is thrown away
+pub const MAX_VERIFICATION_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Clone, Queryable)]
+/// To get data from DB, most columns are only ever compared in SQL
+#[allow(dead_code)]
+pub struct EmailVerification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// Address the code was sent to, the code is useless once the user changes it
+    pub email: String,
+    /// sha256 of user id and code
+    pub code_hash: String,
+    /// Wrong guesses so far
+    pub attempts: i32,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "email_verifications"]
+/// To insert data in DB
+pub struct NewEmailVerification {
+    pub user_id: Uuid,
+    pub email: String,
+    pub code_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// What became of a code a user typed in
+#[derive(Debug)]
+pub enum ConfirmOutcome {
+    /// email is now verified
+    Verified,
+    /// wrong code, the attempt was counted
+    Invalid,
+    /// nothing to confirm: no code was sent, it expired, ran out of attempts or
+    /// was sent to an address the user has since changed
+    Expired,
+}
@@ -1,3 +1,112 @@
+table! {
+    api_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Array<Text>,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_events (id) {
+        id -> Int8,
+        actor_id -> Nullable<Uuid>,
+        action -> Varchar,
+        target_type -> Varchar,
+        target_id -> Nullable<Uuid>,
+        details -> Jsonb,
+        created_at -> Timestamp,
+        user_id -> Nullable<Uuid>,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        ip -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    clicks (id) {
+        id -> Int8,
+        route_id -> Uuid,
+        clicked_at -> Timestamp,
+        referrer -> Nullable<Varchar>,
+        user_agent -> Nullable<Varchar>,
+        ip_hash -> Nullable<Varchar>,
+        browser -> Nullable<Varchar>,
+        os -> Nullable<Varchar>,
+        country -> Nullable<Varchar>,
+        variant_id -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    email_verifications (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        email -> Varchar,
+        code_hash -> Varchar,
+        attempts -> Int4,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    password_resets (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    route_schedules (id) {
+        id -> Uuid,
+        route_id -> Uuid,
+        effective_at -> Timestamp,
+        target -> Varchar,
+        created_by -> Nullable<Uuid>,
+        applied_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        superseded_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    route_targets (id) {
+        id -> Uuid,
+        route_id -> Uuid,
+        target -> Varchar,
+        weight -> Int4,
+        label -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    route_versions (id) {
+        id -> Int8,
+        route_id -> Uuid,
+        version -> Int4,
+        slug -> Varchar,
+        target -> Varchar,
+        active -> Bool,
+        active_from -> Nullable<Timestamp>,
+        active_till -> Nullable<Timestamp>,
+        changed_by -> Nullable<Uuid>,
+        rolled_back_from -> Nullable<Int4>,
+        created_at -> Timestamp,
+        rules -> Jsonb,
+        variants -> Nullable<Jsonb>,
+    }
+}
+
 table! {
     routes (id) {
         id -> Uuid,
@@ -9,6 +118,22 @@ table! {
         active_till -> Nullable<Timestamp>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        visits -> Int8,
+        unique_visits -> Int8,
+        rules -> Jsonb,
+    }
+}
+
+table! {
+    user_sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        user_agent -> Nullable<Varchar>,
+        browser -> Nullable<Varchar>,
+        os -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
     }
 }
 
@@ -23,12 +148,41 @@ table! {
         active -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        session_epoch -> Int4,
+        totp_secret -> Nullable<Bytea>,
+        totp_enabled -> Bool,
+        totp_last_step -> Int8,
+        recovery_codes -> Array<Text>,
+        suspended_at -> Nullable<Timestamp>,
+        suspension_reason -> Nullable<Text>,
+        role -> Varchar,
     }
 }
 
+joinable!(api_tokens -> users (user_id));
+joinable!(audit_events -> users (actor_id));
+joinable!(clicks -> route_targets (variant_id));
+joinable!(clicks -> routes (route_id));
+joinable!(email_verifications -> users (user_id));
+joinable!(password_resets -> users (user_id));
+joinable!(route_schedules -> routes (route_id));
+joinable!(route_schedules -> users (created_by));
+joinable!(route_targets -> routes (route_id));
+joinable!(route_versions -> routes (route_id));
+joinable!(route_versions -> users (changed_by));
 joinable!(routes -> users (creator_id));
+joinable!(user_sessions -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    audit_events,
+    clicks,
+    email_verifications,
+    password_resets,
+    route_schedules,
+    route_targets,
+    route_versions,
     routes,
+    user_sessions,
     users,
 );
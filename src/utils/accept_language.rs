@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+
+/// Languages looked at in a single Accept-Language header
+const MAX_RANGES: usize = 32;
+
+/// Languages of an Accept-Language header, most wanted first and lowercased.
+/// Ones with q=0, a malformed q and "*" are left out
+pub fn parse(header: &str) -> Vec<String> {
+    let mut ranges: Vec<(String, f32)> = Vec::new();
+    for range in header.split(',').take(MAX_RANGES) {
+        let mut params = range.split(';');
+        let tag = params.next().unwrap_or("").trim().to_lowercase();
+        let mut quality = Some(1.0);
+        for param in params {
+            if let Some((name, value)) = param.split_once('=') {
+                if name.trim().eq_ignore_ascii_case("q") {
+                    quality = value.trim().parse::<f32>().ok();
+                }
+            }
+        }
+        match quality {
+            Some(quality) if quality > 0.0 && !tag.is_empty() && tag != "*" => {
+                ranges.push((tag, quality.min(1.0)))
+            }
+            _ => {}
+        }
+    }
+
+    // the sort is stable, so equally wanted languages keep the client's order
+    ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    ranges.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// The available language serving `accepted` best. Going down the accepted
+/// languages, each prefers an exact match, then the closest more general one
+/// ("de" for "de-at"), then any other of the same language ("de-de" for "de")
+pub fn best_match<'a>(accepted: &[String], available: &[&'a str]) -> Option<&'a str> {
+    accepted.iter().find_map(|wanted| {
+        let wanted = wanted.as_str();
+        available
+            .iter()
+            .find(|language| **language == wanted)
+            .or_else(|| {
+                available
+                    .iter()
+                    .filter(|language| is_more_general(language, wanted))
+                    .max_by_key(|language| language.len())
+            })
+            .or_else(|| {
+                available
+                    .iter()
+                    .find(|language| primary(language) == primary(wanted))
+            })
+            .copied()
+    })
+}
+
+fn is_more_general(general: &str, specific: &str) -> bool {
+    specific
+        .strip_prefix(general)
+        .is_some_and(|rest| rest.starts_with('-'))
+}
+
+/// "pt" of "pt-br"
+fn primary(tag: &str) -> &str {
+    tag.split('-').next().unwrap_or(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepted(languages: &[&str]) -> Vec<String> {
+        languages
+            .iter()
+            .map(|language| language.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sorted_by_quality() {
+        assert_eq!(
+            parse("en;q=0.5, DE-at, fr;q=0.8"),
+            accepted(&["de-at", "fr", "en"])
+        );
+    }
+
+    #[test]
+    fn zero_malformed_and_wildcard_are_left_out() {
+        assert_eq!(
+            parse("de;q=0, fr;q=abc, *;q=0.9, es;q=, it;q=0.1"),
+            accepted(&["it"])
+        );
+        assert!(parse("").is_empty());
+        assert!(parse(" , ;q=1").is_empty());
+    }
+
+    #[test]
+    fn equal_quality_keeps_client_order() {
+        assert_eq!(
+            parse("nl;q=0.7, de, sv;q=0.7, fr, da;q=0.7"),
+            accepted(&["de", "fr", "nl", "sv", "da"])
+        );
+        // above 1 counts as 1
+        assert_eq!(parse("en, de;q=2"), accepted(&["en", "de"]));
+    }
+
+    #[test]
+    fn ranges_past_the_limit_are_ignored() {
+        let mut header = (0..MAX_RANGES)
+            .map(|i| format!("x{};q=0.1", i))
+            .collect::<Vec<_>>();
+        header.push("de".to_string());
+        let languages = parse(&header.join(","));
+        assert_eq!(languages.len(), MAX_RANGES);
+        assert!(!languages.contains(&"de".to_string()));
+    }
+
+    #[test]
+    fn exact_match_first() {
+        let available = ["de", "de-at", "de-ch"];
+        assert_eq!(best_match(&accepted(&["de-at"]), &available), Some("de-at"));
+    }
+
+    #[test]
+    fn then_the_closest_more_general() {
+        let available = ["de", "zh-hant", "zh"];
+        assert_eq!(best_match(&accepted(&["de-at"]), &available), Some("de"));
+        assert_eq!(
+            best_match(&accepted(&["zh-hant-tw"]), &available),
+            Some("zh-hant")
+        );
+    }
+
+    #[test]
+    fn then_any_of_the_same_language() {
+        let available = ["pt-br", "pt-pt"];
+        assert_eq!(best_match(&accepted(&["pt"]), &available), Some("pt-br"));
+        assert_eq!(best_match(&accepted(&["pt-ao"]), &available), Some("pt-br"));
+        // "de" is not more general than "dex"
+        assert_eq!(best_match(&accepted(&["dex"]), &["de"]), None);
+    }
+
+    #[test]
+    fn earlier_languages_win_over_better_matches() {
+        let available = ["fr", "de-de"];
+        assert_eq!(
+            best_match(&accepted(&["de-at", "fr"]), &available),
+            Some("de-de")
+        );
+        assert_eq!(best_match(&accepted(&["es", "it"]), &available), None);
+        assert_eq!(best_match(&[], &available), None);
+    }
+}
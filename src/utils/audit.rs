@@ -0,0 +1,24 @@
+use crate::actix::Addr;
+use crate::actors::db::audit::RecordAuditEvent;
+use crate::actors::db::DbActor;
+use crate::models::audit::NewAuditEvent;
+use crate::utils::net::client_ip;
+use actix_web::HttpRequest;
+
+/// Writes `event` to the audit trail with the client IP of `req`.
+///
+/// The audited change has already happened, so a failure is only logged, with
+/// everything the row would have held. Admin actions don't go through here, their
+/// rows are written in the action's own transaction, see `record_in`
+pub async fn record(db: &Addr<DbActor>, req: &HttpRequest, event: NewAuditEvent) {
+    let event = event.ip(client_ip(req));
+    match db
+        .send(RecordAuditEvent {
+            event: event.clone(),
+        })
+        .await
+    {
+        Ok(Ok(_)) => {}
+        _ => error!("could not write audit event {:?}", event),
+    }
+}
@@ -0,0 +1,331 @@
+use crate::actors::db::api_tokens::UseApiToken;
+use crate::actors::db::user_sessions::{CreateUserSession, TouchUserSession};
+use crate::actors::db::users::GetUser;
+use crate::models::user_sessions::NewUserSession;
+use crate::models::users::User;
+use crate::models::AppState;
+use crate::utils::crypto::sha256_hex;
+use crate::utils::net::client_ip;
+use crate::utils::user_agent;
+use actix_session::{Session, UserSession};
+use actix_web::{
+    dev::Payload, error::InternalError, http::header, web::Data, Error, FromRequest, HttpRequest,
+    HttpResponse,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// Seconds a session lives after it was last written, what the session store defaults to
+pub const DEFAULT_SESSION_TTL: u32 = 7200;
+
+/// Prefix of every API token, makes leaked tokens easy to grep for
+pub const TOKEN_PREFIX: &str = "elide_";
+
+/// Session key holding the id of the logged in user
+pub const SESSION_USER_ID: &str = "user_id";
+/// Session key holding `users.session_epoch` as it was at login, sessions from an
+/// older epoch were revoked (e.g. by a password reset)
+pub const SESSION_EPOCH: &str = "session_epoch";
+/// Session key holding the id of the `user_sessions` row, deleting the row logs
+/// the session out
+pub const SESSION_ID: &str = "session_id";
+
+/// Session key holding when the user last typed their password, as a unix timestamp
+pub const SESSION_REAUTH_AT: &str = "reauth_at";
+/// How long after typing their password a user may make sensitive changes without it
+pub const REAUTH_WINDOW_SECS: i64 = 5 * 60;
+
+/// Session key holding the user who passed the password step of a 2FA login
+pub const SESSION_PENDING_USER_ID: &str = "pending_2fa_user_id";
+/// Session key holding when the password step was passed, as a unix timestamp
+pub const SESSION_PENDING_AT: &str = "pending_2fa_at";
+/// Session key set when the password step came from `reactivate_user`, the account
+/// is only reactivated once the second factor is in too
+pub const SESSION_PENDING_REACTIVATE: &str = "pending_2fa_reactivate";
+/// How long the second step of a login may take
+pub const PENDING_WINDOW_SECS: i64 = 5 * 60;
+
+/// Sessions last seen before this have expired in the session store
+pub fn sessions_expired_before(state: &AppState) -> NaiveDateTime {
+    Utc::now().naive_utc() - Duration::seconds(state.session_ttl)
+}
+
+/// Marks the session as logged in for `user` and records where it was started
+pub async fn start_session(
+    req: &HttpRequest,
+    session: &Session,
+    state: &AppState,
+    user: &User,
+) -> Result<(), Error> {
+    let agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let parsed = agent.as_deref().map(user_agent::parse).unwrap_or_default();
+    let tracked = state
+        .db
+        .send(CreateUserSession {
+            session: NewUserSession {
+                user_id: user.id,
+                user_agent: agent,
+                browser: parsed.browser,
+                os: parsed.os,
+                ip: client_ip(req),
+            },
+            expired_before: sessions_expired_before(state),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    session.renew();
+    session.remove(SESSION_PENDING_USER_ID);
+    session.remove(SESSION_PENDING_AT);
+    session.remove(SESSION_PENDING_REACTIVATE);
+    session.set(SESSION_USER_ID, user.id)?;
+    session.set(SESSION_EPOCH, user.session_epoch)?;
+    session.set(SESSION_ID, tracked.id)?;
+    // the password was just checked
+    mark_reauthenticated(session)
+}
+
+/// Marks the session as half way through a 2FA login, it is not logged in yet
+pub fn start_pending_session(
+    session: &Session,
+    user_id: Uuid,
+    reactivate: bool,
+) -> Result<(), Error> {
+    session.renew();
+    session.set(SESSION_PENDING_USER_ID, user_id)?;
+    session.set(SESSION_PENDING_AT, Utc::now().timestamp())?;
+    if reactivate {
+        session.set(SESSION_PENDING_REACTIVATE, true)
+    } else {
+        session.remove(SESSION_PENDING_REACTIVATE);
+        Ok(())
+    }
+}
+
+/// Whether the pending login also reactivates the account
+pub fn pending_reactivation(session: &Session) -> bool {
+    session
+        .get(SESSION_PENDING_REACTIVATE)
+        .unwrap_or(None)
+        .unwrap_or(false)
+}
+
+/// The user waiting for their second factor, None when there is none or it took too long
+pub fn pending_session_user(session: &Session) -> Option<Uuid> {
+    let user_id: Uuid = session.get(SESSION_PENDING_USER_ID).unwrap_or(None)?;
+    let at: i64 = session.get(SESSION_PENDING_AT).unwrap_or(None)?;
+    if Utc::now().timestamp() - at <= PENDING_WINDOW_SECS {
+        Some(user_id)
+    } else {
+        None
+    }
+}
+
+/// Records that the user proved they know their password
+pub fn mark_reauthenticated(session: &Session) -> Result<(), Error> {
+    session.set(SESSION_REAUTH_AT, Utc::now().timestamp())
+}
+
+/// True when the password was checked within the last `REAUTH_WINDOW_SECS`
+pub fn recently_reauthenticated(session: &Session) -> bool {
+    let reauth_at: Option<i64> = session.get(SESSION_REAUTH_AT).unwrap_or(None);
+    reauth_at.is_some_and(|at| Utc::now().timestamp() - at <= REAUTH_WINDOW_SECS)
+}
+
+/// The user a request acts for, authenticated by the session cookie alone.
+/// For endpoints an API token must never reach, like token management
+pub struct SessionUser {
+    pub id: Uuid,
+    /// row in `user_sessions`, None for sessions started before they were tracked
+    pub session_id: Option<Uuid>,
+}
+
+/// An admin, authenticated by the session cookie alone, guards /api/admin/
+pub struct AdminUser {
+    pub id: Uuid,
+}
+
+/// The user a request acts for, authenticated by the session cookie or by an
+/// `Authorization: Bearer` API token
+pub struct AuthUser {
+    pub id: Uuid,
+    /// scopes of the API token used, None for cookie sessions which may do anything
+    pub scopes: Option<Vec<String>>,
+}
+
+impl AuthUser {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.iter().any(|granted| granted == scope),
+            None => true,
+        }
+    }
+
+    /// 403 response for handlers to return when `has_scope` fails
+    pub fn missing_scope(scope: &str) -> HttpResponse {
+        HttpResponse::Forbidden().json(format!("Token is missing the \"{}\" scope", scope))
+    }
+}
+
+fn unauthorized() -> Error {
+    InternalError::from_response(
+        "Unauthorized",
+        HttpResponse::Unauthorized().json("Unauthorized"),
+    )
+    .into()
+}
+
+/// Checks the session still belongs to an active user and was not revoked since login
+async fn session_user(
+    session: Session,
+    state: Option<Data<AppState>>,
+) -> Result<(SessionUser, User), Error> {
+    let user_id: Uuid = session
+        .get(SESSION_USER_ID)
+        .unwrap_or(None)
+        .ok_or_else(unauthorized)?;
+    // sessions from before epochs existed count as epoch 0
+    let epoch: i32 = session.get(SESSION_EPOCH).unwrap_or(None).unwrap_or(0);
+    let session_id: Option<Uuid> = session.get(SESSION_ID).unwrap_or(None);
+
+    let state = state.ok_or_else(unauthorized)?;
+    let db = &state.db;
+    let user = match db.send(GetUser { id: user_id }).await {
+        Ok(Ok(user)) if user.active && user.session_epoch == epoch => Some(user),
+        Ok(_) => None,
+        Err(err) => return Err(actix_web::error::ErrorInternalServerError(err)),
+    };
+    let user = match (user, session_id) {
+        (Some(user), Some(id)) => match db
+            .send(TouchUserSession {
+                id,
+                user_id,
+                expired_before: sessions_expired_before(&state),
+            })
+            .await
+        {
+            Ok(result) => result.ok().map(|_| user),
+            Err(err) => return Err(actix_web::error::ErrorInternalServerError(err)),
+        },
+        (user, _) => user,
+    };
+
+    match user {
+        Some(user) => Ok((
+            SessionUser {
+                id: user_id,
+                session_id,
+            },
+            user,
+        )),
+        None => {
+            session.purge();
+            Err(unauthorized())
+        }
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_at(value.find(' ')?);
+    if scheme.eq_ignore_ascii_case("bearer") {
+        Some(token.trim().to_string())
+    } else {
+        None
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = bearer_token(req);
+        let session = req.get_session();
+        let state = req.app_data::<Data<AppState>>().cloned();
+
+        Box::pin(async move {
+            // a bad token is refused even if a session cookie came along with it
+            let token = match token {
+                Some(token) => token,
+                None => {
+                    return session_user(session, state.clone())
+                        .await
+                        .map(|(user, _)| AuthUser {
+                            id: user.id,
+                            scopes: None,
+                        })
+                }
+            };
+            if !token.starts_with(TOKEN_PREFIX) {
+                return Err(unauthorized());
+            }
+
+            let state = state.ok_or_else(unauthorized)?;
+            match state
+                .db
+                .send(UseApiToken {
+                    token_hash: sha256_hex(token.as_bytes()),
+                })
+                .await
+            {
+                Ok(Ok(token)) => Ok(AuthUser {
+                    id: token.user_id,
+                    scopes: Some(token.scopes),
+                }),
+                Ok(Err(_)) => Err(unauthorized()),
+                Err(err) => Err(actix_web::error::ErrorInternalServerError(err)),
+            }
+        })
+    }
+}
+
+impl FromRequest for SessionUser {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let session = req.get_session();
+        let state = req.app_data::<Data<AppState>>().cloned();
+
+        Box::pin(async move {
+            session_user(session, state.clone())
+                .await
+                .map(|(user, _)| user)
+        })
+    }
+}
+
+impl FromRequest for AdminUser {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let session = req.get_session();
+        let state = req.app_data::<Data<AppState>>().cloned();
+
+        Box::pin(async move {
+            let (session_user, user) = session_user(session, state.clone()).await?;
+            if !user.is_admin() {
+                return Err(InternalError::from_response(
+                    "Forbidden",
+                    HttpResponse::Forbidden().json("Admins only"),
+                )
+                .into());
+            }
+            Ok(AdminUser {
+                id: session_user.id,
+            })
+        })
+    }
+}
@@ -1,4 +1,5 @@
 use rand::prelude::*;
+use sodiumoxide::crypto::hash::sha256;
 use sodiumoxide::crypto::pwhash::argon2id13;
 
 pub fn hash(passwd: String) -> (String, argon2id13::HashedPassword) {
@@ -31,3 +32,39 @@ pub fn random_redis_key() -> [u8; 32] {
     rand::thread_rng().fill_bytes(&mut key);
     key
 }
+
+// hex encoded sha256, only for high entropy input, passwords go through `hash`
+pub fn sha256_hex(data: &[u8]) -> String {
+    sodiumoxide::init().unwrap();
+    let digest = sha256::hash(data);
+    digest
+        .0
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Shortest salt accepted for hashing client IPs, an IPv4 address alone is brute
+/// forced in seconds
+pub const MIN_SALT_LENGTH: usize = 32;
+
+// salted sha256 of the client IP, hex encoded, so visitors can be counted without storing IPs
+pub fn hash_ip(ip: &str, salt: &str) -> String {
+    sha256_hex(format!("{}{}", salt, ip).as_bytes())
+}
+
+// random alphanumeric string with `prefix` in front, for tokens handed to users
+pub fn random_token(prefix: &str, length: usize) -> String {
+    let token: String = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect();
+    format!("{}{}", prefix, token)
+}
+
+// random numeric code of `digits` digits, for codes users type in by hand
+pub fn random_code(digits: u32) -> String {
+    let code = rand::thread_rng().gen_range(0..10u32.pow(digits));
+    format!("{:0width$}", code, width = digits as usize)
+}
@@ -11,9 +11,10 @@ pub fn run_migrations(db_url: &str) {
         .expect("Error running migrations");
 }
 
-pub fn get_pool(db_url: &str) -> Pool<ConnectionManager<PgConnection>> {
+pub fn get_pool(db_url: &str, size: u32) -> Pool<ConnectionManager<PgConnection>> {
     let manager = ConnectionManager::<PgConnection>::new(db_url);
     Pool::builder()
+        .max_size(size)
         .build(manager)
         .expect("Error building a connection pool")
 }
@@ -0,0 +1,54 @@
+use maxminddb::{geoip2, Reader};
+use std::fmt;
+use std::net::IpAddr;
+
+/// Where a client IP is located, as far as the database knows
+#[derive(Debug, Default)]
+pub struct Location {
+    /// ISO 3166-1 alpha-2 code e.g. "DE"
+    pub country: Option<String>,
+    /// two letter continent code e.g. "EU"
+    pub continent: Option<String>,
+}
+
+/// Country lookups in a local MaxMind database (GeoLite2/GeoIP2 Country or City),
+/// the file is read into memory once and never leaves the machine
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeoIp").finish()
+    }
+}
+
+impl GeoIp {
+    pub fn open(path: &str) -> Result<Self, String> {
+        Reader::open_readfile(path)
+            .map(|reader| GeoIp { reader })
+            .map_err(|err| format!("could not open GeoIP database {}: {}", path, err))
+    }
+
+    /// Unknown, private and malformed addresses give an empty location
+    pub fn lookup(&self, ip: &str) -> Location {
+        let ip = match ip.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return Location::default(),
+        };
+
+        match self.reader.lookup::<geoip2::Country>(ip) {
+            Ok(record) => Location {
+                country: record
+                    .country
+                    .and_then(|country| country.iso_code)
+                    .map(str::to_string),
+                continent: record
+                    .continent
+                    .and_then(|continent| continent.code)
+                    .map(str::to_string),
+            },
+            Err(_) => Location::default(),
+        }
+    }
+}
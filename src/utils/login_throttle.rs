@@ -0,0 +1,350 @@
+use actix::Addr;
+use actix_redis::{Command, RedisActor, RespValue};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Attempts without a success allowed before the first lockout
+pub const DEFAULT_FREE_ATTEMPTS: u64 = 5;
+/// First lockout, doubled with every further attempt
+pub const DEFAULT_BASE_LOCKOUT: Duration = Duration::from_secs(1);
+/// Longest lockout
+pub const DEFAULT_MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// Attempts are forgotten this long after the last one
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Entries kept in memory before expired ones are swept
+const MEMORY_SWEEP_THRESHOLD: usize = 10_000;
+
+/// When repeated login attempts turn into lockouts
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottlePolicy {
+    pub free_attempts: u64,
+    pub base_lockout: Duration,
+    pub max_lockout: Duration,
+    pub window: Duration,
+}
+
+impl ThrottlePolicy {
+    /// Lockout started by the `attempts`th attempt without a success, None while still free
+    fn lockout(&self, attempts: u64) -> Option<Duration> {
+        let over = attempts.checked_sub(self.free_attempts + 1)?;
+        let factor = 2u32.checked_pow(over.min(u32::MAX as u64) as u32);
+        let lockout = factor
+            .and_then(|factor| self.base_lockout.checked_mul(factor))
+            .unwrap_or(self.max_lockout);
+        Some(lockout.min(self.max_lockout))
+    }
+}
+
+struct Entry {
+    attempts: u64,
+    locked_until: Option<Instant>,
+    forget_at: Instant,
+}
+
+/// Counts login attempts per key (username or client IP) and locks keys out with
+/// exponential backoff. An attempt is counted before the password is checked, in
+/// the same step that tests the lockout, so parallel guesses can't slip through;
+/// a success clears or refunds it again.
+///
+/// State lives in Redis so every worker and instance agrees, an in-process map
+/// is used without Redis and whenever a Redis command fails.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    policy: ThrottlePolicy,
+    redis: Option<Addr<RedisActor>>,
+    memory: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+pub fn username_key(username: &str) -> String {
+    format!("elide:login:user:{}", username.to_lowercase())
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("elide:login:ip:{}", ip)
+}
+
+/// Password confirmations of a logged in user, a stolen cookie must not allow guessing
+pub fn reauth_key(user_id: Uuid) -> String {
+    format!("elide:login:reauth:{}", user_id)
+}
+
+fn lock_key(key: &str) -> String {
+    format!("{}:lock", key)
+}
+
+fn command(args: &[&str]) -> Command {
+    Command(RespValue::Array(
+        args.iter()
+            .map(|arg| RespValue::BulkString(arg.as_bytes().to_vec()))
+            .collect(),
+    ))
+}
+
+impl LoginThrottle {
+    pub fn new(policy: ThrottlePolicy, redis: Option<Addr<RedisActor>>) -> Self {
+        LoginThrottle {
+            policy,
+            redis,
+            memory: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Counts an attempt on every key, or none of them when one is locked out.
+    /// Err holds the longest remaining lockout
+    pub async fn attempt(&self, keys: &[String]) -> Result<(), Duration> {
+        for (index, key) in keys.iter().enumerate() {
+            if let Err(wait) = self.attempt_one(key).await {
+                for taken in &keys[..index] {
+                    self.refund(taken).await;
+                }
+                return Err(wait);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets the attempts of `key`, after a successful login
+    pub async fn clear(&self, key: &str) {
+        if let Some(redis) = &self.redis {
+            if let Err(err) = run(redis, &["DEL", key, &lock_key(key)]).await {
+                warn!("login throttle could not clear {}: {}", key, err);
+            }
+        }
+        self.memory.lock().unwrap().remove(key);
+    }
+
+    /// Takes back one attempt of `key` without lifting a lockout, for keys such as
+    /// the client IP where one success must not reset the count
+    pub async fn refund(&self, key: &str) {
+        if let Some(redis) = &self.redis {
+            match run(redis, &["EVAL", REFUND_SCRIPT, "1", key]).await {
+                Ok(_) => return,
+                Err(err) => warn!("login throttle falling back to memory: {}", err),
+            }
+        }
+        self.refund_memory(key);
+    }
+
+    async fn attempt_one(&self, key: &str) -> Result<(), Duration> {
+        let result = match &self.redis {
+            Some(redis) => match self.attempt_redis(redis, key).await {
+                Ok(result) => result,
+                Err(err) => {
+                    warn!("login throttle falling back to memory: {}", err);
+                    self.attempt_memory(key, Instant::now())
+                }
+            },
+            None => self.attempt_memory(key, Instant::now()),
+        };
+
+        if let Ok(Some(lockout)) = result {
+            warn!(
+                "login lockout: {} locked for {}s after repeated attempts",
+                key,
+                lockout.as_secs_f64().ceil()
+            );
+        }
+        result.map(|_| ())
+    }
+
+    /// Ok with the lockout this attempt started if any, Err while locked out
+    async fn attempt_redis(
+        &self,
+        redis: &Addr<RedisActor>,
+        key: &str,
+    ) -> Result<Result<Option<Duration>, Duration>, String> {
+        let lock = lock_key(key);
+        let window = self.policy.window.as_millis().to_string();
+        let free = self.policy.free_attempts.to_string();
+        let base = self.policy.base_lockout.as_millis().to_string();
+        let max = self.policy.max_lockout.as_millis().to_string();
+        let args = [
+            "EVAL",
+            ATTEMPT_SCRIPT,
+            "2",
+            key,
+            &lock,
+            &window,
+            &free,
+            &base,
+            &max,
+        ];
+        match run(redis, &args).await? {
+            RespValue::Integer(ms) if ms < 0 => Ok(Err(Duration::from_millis(-ms as u64))),
+            RespValue::Integer(attempts) => Ok(Ok(self.policy.lockout(attempts as u64))),
+            other => Err(format!("unexpected EVAL reply {:?}", other)),
+        }
+    }
+
+    fn attempt_memory(&self, key: &str, now: Instant) -> Result<Option<Duration>, Duration> {
+        let mut memory = self.memory.lock().unwrap();
+        if memory.len() >= MEMORY_SWEEP_THRESHOLD {
+            memory.retain(|_, entry| entry.forget_at > now);
+        }
+
+        let entry = memory.entry(key.to_string()).or_insert(Entry {
+            attempts: 0,
+            locked_until: None,
+            forget_at: now,
+        });
+        if let Some(until) = entry.locked_until.filter(|until| *until > now) {
+            return Err(until - now);
+        }
+        if entry.forget_at <= now {
+            entry.attempts = 0;
+            entry.locked_until = None;
+        }
+        entry.attempts += 1;
+        entry.forget_at = now + self.policy.window;
+
+        let lockout = self.policy.lockout(entry.attempts);
+        if let Some(lockout) = lockout {
+            entry.locked_until = Some(now + lockout);
+        }
+        Ok(lockout)
+    }
+
+    fn refund_memory(&self, key: &str) {
+        if let Some(entry) = self.memory.lock().unwrap().get_mut(key) {
+            entry.attempts = entry.attempts.saturating_sub(1);
+        }
+    }
+}
+
+/// Tests the lock and counts the attempt in one step, locking like
+/// `ThrottlePolicy::lockout` once the free attempts are used up. Returns the
+/// negated remaining lockout in ms, or the attempt count
+const ATTEMPT_SCRIPT: &str = r"
+local ttl = redis.call('PTTL', KEYS[2])
+if ttl > 0 then return -ttl end
+local attempts = redis.call('INCR', KEYS[1])
+redis.call('PEXPIRE', KEYS[1], ARGV[1])
+local over = attempts - tonumber(ARGV[2]) - 1
+if over >= 0 then
+    local ms = math.min(tonumber(ARGV[3]) * 2 ^ over, tonumber(ARGV[4]))
+    redis.call('SET', KEYS[2], '1', 'PX', math.max(math.floor(ms), 1))
+end
+return attempts
+";
+
+/// Decrements the count if there is one, the lock is left alone
+const REFUND_SCRIPT: &str = r"
+local attempts = tonumber(redis.call('GET', KEYS[1]) or '0')
+if attempts > 0 then redis.call('DECR', KEYS[1]) end
+return 0
+";
+
+async fn run(redis: &Addr<RedisActor>, args: &[&str]) -> Result<RespValue, String> {
+    match redis.send(command(args)).await {
+        Ok(Ok(RespValue::Error(err))) => Err(err),
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ThrottlePolicy {
+        ThrottlePolicy {
+            free_attempts: 3,
+            base_lockout: Duration::from_secs(1),
+            max_lockout: Duration::from_secs(10),
+            window: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn lockout_starts_after_free_attempts_and_doubles() {
+        let policy = policy();
+        assert_eq!(policy.lockout(3), None);
+        assert_eq!(policy.lockout(4), Some(Duration::from_secs(1)));
+        assert_eq!(policy.lockout(5), Some(Duration::from_secs(2)));
+        assert_eq!(policy.lockout(7), Some(Duration::from_secs(8)));
+        assert_eq!(policy.lockout(8), Some(Duration::from_secs(10)));
+        assert_eq!(policy.lockout(u64::MAX), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn memory_locks_out_at_the_threshold() {
+        let throttle = LoginThrottle::new(policy(), None);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(throttle.attempt_memory("key", now), Ok(None));
+        }
+        assert_eq!(
+            throttle.attempt_memory("key", now),
+            Ok(Some(Duration::from_secs(1)))
+        );
+        // parallel attempts are turned away while the lockout runs
+        assert_eq!(
+            throttle.attempt_memory("key", now),
+            Err(Duration::from_secs(1))
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            throttle.attempt_memory("key", later),
+            Ok(Some(Duration::from_secs(2)))
+        );
+        assert!(throttle.attempt_memory("other", later).is_ok());
+    }
+
+    #[test]
+    fn memory_forgets_after_the_window() {
+        let throttle = LoginThrottle::new(policy(), None);
+        let now = Instant::now();
+        for _ in 0..4 {
+            throttle.attempt_memory("key", now).unwrap();
+        }
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(throttle.attempt_memory("key", later), Ok(None));
+    }
+
+    #[actix_rt::test]
+    async fn success_resets_the_count() {
+        let throttle = LoginThrottle::new(policy(), None);
+        let now = Instant::now();
+        for _ in 0..3 {
+            throttle.attempt_memory("key", now).unwrap();
+        }
+        throttle.clear("key").await;
+        assert_eq!(throttle.attempt_memory("key", now), Ok(None));
+    }
+
+    #[actix_rt::test]
+    async fn refund_keeps_the_lockout() {
+        let throttle = LoginThrottle::new(policy(), None);
+        let now = Instant::now();
+        for _ in 0..3 {
+            throttle.attempt_memory("key", now).unwrap();
+        }
+        throttle.refund("key").await;
+        assert_eq!(throttle.attempt_memory("key", now), Ok(None));
+
+        throttle.attempt_memory("key", now).unwrap();
+        throttle.refund("key").await;
+        assert!(throttle.attempt_memory("key", now).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn attempt_takes_nothing_when_one_key_is_locked() {
+        let throttle = LoginThrottle::new(policy(), None);
+        let now = Instant::now();
+        for _ in 0..4 {
+            throttle.attempt_memory("ip", now).unwrap();
+        }
+
+        let keys = ["user".to_string(), "ip".to_string()];
+        assert!(throttle.attempt(&keys).await.is_err());
+        for _ in 0..3 {
+            assert_eq!(throttle.attempt_memory("user", now), Ok(None));
+        }
+    }
+}
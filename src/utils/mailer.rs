@@ -0,0 +1,99 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{Message, SmtpTransport, Transport};
+
+/// A plain text email
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Something that can deliver mail, sending may block so it runs on the MailActor
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), String>;
+}
+
+/// Writes mails to the log instead of sending them, for development
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        info!(
+            "mail to {}\nSubject: {}\n\n{}",
+            mail.to, mail.subject, mail.body
+        );
+        Ok(())
+    }
+}
+
+/// How the SMTP connection is secured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    /// plaintext, only for local catchers like MailHog
+    None,
+    /// plaintext connection upgraded with STARTTLS
+    StartTls,
+    /// TLS from the start (SMTPS)
+    Tls,
+}
+
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        security: SmtpSecurity,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, String> {
+        let from = from
+            .parse::<Mailbox>()
+            .map_err(|err| format!("invalid from address \"{}\": {}", from, err))?;
+
+        let tls = match security {
+            SmtpSecurity::None => Tls::None,
+            SmtpSecurity::StartTls => Tls::Required(tls_parameters(host)?),
+            SmtpSecurity::Tls => Tls::Wrapper(tls_parameters(host)?),
+        };
+        let mut builder = SmtpTransport::builder_dangerous(host).port(port).tls(tls);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+fn tls_parameters(host: &str) -> Result<TlsParameters, String> {
+    TlsParameters::new(host.to_string()).map_err(|err| format!("smtp tls: {}", err))
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        let to = mail
+            .to
+            .parse::<Mailbox>()
+            .map_err(|err| format!("invalid recipient \"{}\": {}", mail.to, err))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(mail.subject.as_str())
+            .body(mail.body.clone())
+            .map_err(|err| err.to_string())?;
+
+        self.transport
+            .send(&message)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+}
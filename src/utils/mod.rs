@@ -1,2 +1,16 @@
+pub mod accept_language;
+pub mod audit;
+pub mod auth;
 pub mod crypto;
 pub mod db;
+pub mod geoip;
+pub mod login_throttle;
+pub mod mailer;
+pub mod net;
+pub mod reserved;
+pub mod rules;
+pub mod slug;
+pub mod split;
+pub mod target;
+pub mod totp;
+pub mod user_agent;
@@ -0,0 +1,176 @@
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+/// Proxies trusted when nothing else is configured, nginx on the same machine
+pub const DEFAULT_TRUSTED_PROXIES: &[&str] = &["127.0.0.1", "::1"];
+
+static TRUSTED_PROXIES: OnceLock<Vec<IpNet>> = OnceLock::new();
+
+/// Parses addresses ("10.0.0.1") and networks ("10.0.0.0/8")
+pub fn parse_proxies<S: AsRef<str>>(proxies: &[S]) -> Result<Vec<IpNet>, String> {
+    proxies
+        .iter()
+        .map(|proxy| {
+            let proxy = proxy.as_ref().trim();
+            proxy
+                .parse::<IpNet>()
+                .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("\"{}\" is not an IP address or network", proxy))
+        })
+        .collect()
+}
+
+/// Sets the proxies whose forwarding headers are believed, only the first call has
+/// any effect
+pub fn init(proxies: Vec<IpNet>) {
+    let _ = TRUSTED_PROXIES.set(proxies);
+}
+
+/// IP of the client without the port. X-Forwarded-For and X-Real-IP are only
+/// believed when the connection comes from a trusted proxy, see `resolve`
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let trusted = TRUSTED_PROXIES.get().map(Vec::as_slice).unwrap_or_default();
+    let headers = req.headers();
+    let forwarded_for = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    let forwarded_for = Some(forwarded_for.as_str()).filter(|value| !value.is_empty());
+    let real_ip = headers
+        .get("x-real-ip")
+        .and_then(|value| value.to_str().ok());
+
+    Some(resolve(peer, forwarded_for, real_ip, trusted).to_string())
+}
+
+/// Walks X-Forwarded-For from the right, every trusted proxy appended the address
+/// it got the request from, so the first untrusted one is the client. Entries left
+/// of it are whatever the client sent and are ignored
+fn resolve(
+    peer: IpAddr,
+    forwarded_for: Option<&str>,
+    real_ip: Option<&str>,
+    trusted: &[IpNet],
+) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let forwarded_for = match forwarded_for {
+        Some(forwarded_for) => forwarded_for,
+        None => {
+            return real_ip
+                .and_then(|ip| strip_port(ip.trim()).parse().ok())
+                .unwrap_or(peer)
+        }
+    };
+
+    let mut client = peer;
+    for hop in forwarded_for.rsplit(',') {
+        match strip_port(hop.trim()).parse::<IpAddr>() {
+            Ok(ip) if is_trusted(&ip) => client = ip,
+            Ok(ip) => return ip,
+            // garbage can only come from the client or a broken proxy, stop at the last good hop
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+/// "1.2.3.4:5678" -> "1.2.3.4", "[::1]:80" -> "::1", bare addresses pass through
+fn strip_port(addr: &str) -> &str {
+    if let Some(rest) = addr.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match addr.rfind(':') {
+        // more than one colon means a bare IPv6 address
+        Some(idx) if addr[..idx].find(':').is_none() => &addr[..idx],
+        _ => addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn proxies() -> Vec<IpNet> {
+        parse_proxies(&["127.0.0.1", "10.0.0.0/8"]).unwrap()
+    }
+
+    #[test]
+    fn untrusted_peer_headers_are_ignored() {
+        let client = resolve(
+            ip("203.0.113.7"),
+            Some("1.1.1.1"),
+            Some("2.2.2.2"),
+            &proxies(),
+        );
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn rightmost_untrusted_hop_is_the_client() {
+        // the client sent a fake "1.1.1.1", nginx appended the real peer
+        let client = resolve(
+            ip("127.0.0.1"),
+            Some("1.1.1.1, 203.0.113.7, 10.1.2.3"),
+            None,
+            &proxies(),
+        );
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn only_trusted_hops_give_the_leftmost() {
+        let client = resolve(ip("127.0.0.1"), Some("10.0.0.2,10.0.0.1"), None, &proxies());
+        assert_eq!(client, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn garbage_stops_the_walk() {
+        let client = resolve(
+            ip("127.0.0.1"),
+            Some("203.0.113.7, nonsense"),
+            None,
+            &proxies(),
+        );
+        assert_eq!(client, ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn real_ip_without_forwarded_for() {
+        let client = resolve(ip("127.0.0.1"), None, Some("203.0.113.7"), &proxies());
+        assert_eq!(client, ip("203.0.113.7"));
+        let client = resolve(ip("127.0.0.1"), None, None, &proxies());
+        assert_eq!(client, ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn nothing_trusted_without_proxies() {
+        let client = resolve(ip("127.0.0.1"), Some("203.0.113.7"), None, &[]);
+        assert_eq!(client, ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn ports_are_stripped() {
+        assert_eq!(strip_port("1.2.3.4:5678"), "1.2.3.4");
+        assert_eq!(strip_port("[::1]:80"), "::1");
+        assert_eq!(strip_port("2001:db8::1"), "2001:db8::1");
+        assert_eq!(strip_port("1.2.3.4"), "1.2.3.4");
+    }
+
+    #[test]
+    fn proxies_parse() {
+        assert!(parse_proxies(&["::1", "fd00::/8", " 192.168.0.0/16 "]).is_ok());
+        assert!(parse_proxies(&["localhost"]).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Slugs that would shadow our own paths or well known files served from the root
+pub const DEFAULT_RESERVED: &[&str] = &[
+    ".well-known",
+    "about",
+    "account",
+    "admin",
+    "ads.txt",
+    "api",
+    "apple-app-site-association",
+    "assets",
+    "auth",
+    "console",
+    "dashboard",
+    "docs",
+    "favicon.ico",
+    "health",
+    "help",
+    "humans.txt",
+    "login",
+    "logout",
+    "metrics",
+    "oauth",
+    "register",
+    "robots.txt",
+    "security.txt",
+    "settings",
+    "signup",
+    "sitemap.xml",
+    "static",
+    "www",
+];
+
+/// Defaults the app routes itself, these stay reserved whatever the config says
+pub const ROUTED: &[&str] = &["api"];
+
+static RESERVED: OnceLock<HashSet<String>> = OnceLock::new();
+
+/// Sets up the registry with the defaults but `allowed`, plus `extra`. Only the first
+/// call has any effect
+pub fn init<S: AsRef<str>>(extra: &[S], allowed: &[S]) {
+    let _ = RESERVED.set(registry(extra, allowed));
+}
+
+/// The defaults but `allowed`, plus `extra`, lowercased
+fn registry<S: AsRef<str>>(extra: &[S], allowed: &[S]) -> HashSet<String> {
+    let allowed: HashSet<String> = allowed
+        .iter()
+        .map(|slug| slug.as_ref().trim().to_lowercase())
+        .collect();
+    DEFAULT_RESERVED
+        .iter()
+        .map(|slug| slug.to_string())
+        .filter(|slug| !allowed.contains(slug))
+        .chain(extra.iter().map(|slug| slug.as_ref().trim().to_lowercase()))
+        .filter(|slug| !slug.is_empty())
+        .collect()
+}
+
+/// Case insensitive, falls back to the defaults if `init` was never called
+pub fn is_reserved(slug: &str) -> bool {
+    let slug = slug.to_lowercase();
+    match RESERVED.get() {
+        Some(reserved) => reserved.contains(&slug),
+        None => DEFAULT_RESERVED.contains(&slug.as_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tests never call `init`, so `is_reserved` sees the defaults
+
+    #[test]
+    fn defaults_are_reserved_in_any_case() {
+        for slug in &[
+            "api",
+            "API",
+            "Login",
+            "admin",
+            "ADMIN",
+            "robots.txt",
+            "Robots.TXT",
+            "favicon.ico",
+        ] {
+            assert!(is_reserved(slug), "{}", slug);
+        }
+        assert!(!is_reserved("my-link"));
+    }
+
+    #[test]
+    fn extra_slugs_extend_the_defaults() {
+        let reserved = registry(&[" Pricing ", "", "blog"], &[]);
+        assert!(reserved.contains("pricing"));
+        assert!(reserved.contains("blog"));
+        assert!(!reserved.contains(""));
+        for slug in DEFAULT_RESERVED {
+            assert!(reserved.contains(*slug), "{}", slug);
+        }
+        assert_eq!(reserved.len(), DEFAULT_RESERVED.len() + 2);
+    }
+
+    #[test]
+    fn allowed_defaults_are_free() {
+        let reserved = registry(&["blog"], &["Docs", "help"]);
+        assert!(!reserved.contains("docs"));
+        assert!(!reserved.contains("help"));
+        assert!(reserved.contains("blog"));
+        assert!(reserved.contains("login"));
+    }
+}
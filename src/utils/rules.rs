@@ -0,0 +1,161 @@
+use crate::models::route_rules::{DeviceRule, GeoRule, LanguageRule, RouteRule, RouteRules};
+use crate::utils::accept_language;
+use crate::utils::geoip::Location;
+use crate::utils::user_agent::UserAgentInfo;
+
+/// What route rules can match a request on
+pub struct RequestInfo<'a> {
+    pub agent: &'a UserAgentInfo,
+    /// empty without a GeoIP database
+    pub location: &'a Location,
+    /// from Accept-Language, most wanted first
+    pub languages: &'a [String],
+}
+
+/// Target of the first rule matching the request
+pub fn evaluate<'a>(rules: &'a RouteRules, request: &RequestInfo) -> Option<&'a str> {
+    rules.0.iter().find_map(|rule| match rule {
+        RouteRule::Device(rule) => {
+            device_matches(rule, request.agent).then_some(rule.target.as_str())
+        }
+        RouteRule::Geo(rule) => geo_matches(rule, request.location).then_some(rule.target.as_str()),
+        RouteRule::Language(rule) => language_target(rule, request.languages),
+    })
+}
+
+fn device_matches(rule: &DeviceRule, agent: &UserAgentInfo) -> bool {
+    (rule.os.is_empty() || agent.os_family.is_some_and(|os| rule.os.contains(&os)))
+        && (rule.device.is_empty()
+            || agent
+                .device
+                .is_some_and(|device| rule.device.contains(&device)))
+        && (rule.browser.is_empty()
+            || agent.browser.as_deref().is_some_and(|browser| {
+                rule.browser
+                    .iter()
+                    .any(|wanted| wanted.trim().eq_ignore_ascii_case(browser))
+            }))
+        && rule.bot.is_none_or(|bot| bot == agent.bot)
+}
+
+fn geo_matches(rule: &GeoRule, location: &Location) -> bool {
+    let listed = |codes: &[String], code: &Option<String>| {
+        code.as_ref().is_some_and(|code| codes.contains(code))
+    };
+
+    listed(&rule.country, &location.country) || listed(&rule.continent, &location.continent)
+}
+
+fn language_target<'a>(rule: &'a LanguageRule, accepted: &[String]) -> Option<&'a str> {
+    let available: Vec<&str> = rule.languages.keys().map(String::as_str).collect();
+
+    accept_language::best_match(accepted, &available)
+        .and_then(|language| rule.languages.get(language))
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::user_agent::{self, OsFamily};
+
+    const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 16_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.5 Mobile/15E148 Safari/604.1";
+    const WINDOWS: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36";
+
+    fn ios(target: &str) -> RouteRule {
+        RouteRule::Device(DeviceRule {
+            os: vec![OsFamily::Ios],
+            device: vec![],
+            browser: vec![],
+            bot: None,
+            target: target.to_string(),
+        })
+    }
+
+    fn chrome(target: &str) -> RouteRule {
+        RouteRule::Device(DeviceRule {
+            os: vec![],
+            device: vec![],
+            browser: vec!["chrome".to_string()],
+            bot: None,
+            target: target.to_string(),
+        })
+    }
+
+    fn german(target: &str) -> RouteRule {
+        let mut languages = std::collections::BTreeMap::new();
+        languages.insert("de".to_string(), target.to_string());
+        RouteRule::Language(LanguageRule { languages })
+    }
+
+    fn target<'a>(rules: &'a RouteRules, agent: &str, languages: &[&str]) -> Option<&'a str> {
+        let languages: Vec<String> = languages.iter().map(|l| l.to_string()).collect();
+        evaluate(
+            rules,
+            &RequestInfo {
+                agent: &user_agent::parse(agent),
+                location: &Location::default(),
+                languages: &languages,
+            },
+        )
+    }
+
+    #[test]
+    fn no_rules_no_target() {
+        assert_eq!(target(&RouteRules::default(), IPHONE, &["de"]), None);
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let rules = RouteRules(vec![german("https://de"), ios("https://ios")]);
+        assert_eq!(target(&rules, IPHONE, &["de"]), Some("https://de"));
+        assert_eq!(target(&rules, IPHONE, &["en"]), Some("https://ios"));
+
+        let rules = RouteRules(vec![ios("https://ios"), german("https://de")]);
+        assert_eq!(target(&rules, IPHONE, &["de"]), Some("https://ios"));
+        assert_eq!(target(&rules, WINDOWS, &["de"]), Some("https://de"));
+    }
+
+    fn geo(country: &[&str], continent: &[&str]) -> GeoRule {
+        GeoRule {
+            country: country.iter().map(|code| code.to_string()).collect(),
+            continent: continent.iter().map(|code| code.to_string()).collect(),
+            target: "https://geo".to_string(),
+        }
+    }
+
+    fn location(country: Option<&str>, continent: Option<&str>) -> Location {
+        Location {
+            country: country.map(str::to_string),
+            continent: continent.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn geo_matches_country_or_continent() {
+        let rule = geo(&["DE", "AT"], &["OC"]);
+        assert!(geo_matches(&rule, &location(Some("DE"), Some("EU"))));
+        assert!(geo_matches(&rule, &location(Some("AU"), Some("OC"))));
+        assert!(!geo_matches(&rule, &location(Some("FR"), Some("EU"))));
+        // without a GeoIP database nothing is known
+        assert!(!geo_matches(&rule, &Location::default()));
+    }
+
+    #[test]
+    fn geo_matches_only_listed_kinds() {
+        let continent_only = geo(&[], &["EU"]);
+        assert!(geo_matches(&continent_only, &location(None, Some("EU"))));
+        assert!(!geo_matches(&continent_only, &location(Some("EU"), None)));
+
+        let country_only = geo(&["US"], &[]);
+        assert!(geo_matches(&country_only, &location(Some("US"), None)));
+        assert!(!geo_matches(&country_only, &location(None, Some("NA"))));
+    }
+
+    #[test]
+    fn nothing_matching_no_target() {
+        let rules = RouteRules(vec![ios("https://ios"), chrome("https://chrome")]);
+        assert_eq!(target(&rules, WINDOWS, &[]), Some("https://chrome"));
+        assert_eq!(target(&rules, "curl/7.68.0", &["de"]), None);
+    }
+}
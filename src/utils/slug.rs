@@ -0,0 +1,64 @@
+use rand::Rng;
+
+/// Easy to read and type, no 0/O, 1/l/I
+pub const DEFAULT_ALPHABET: &str = "abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+pub const DEFAULT_LENGTH: usize = 6;
+/// Give up after this many slug collisions in a single request
+pub const MAX_ATTEMPTS: usize = 8;
+/// Grow the slug by one character after this many collisions in a row
+pub const COLLISIONS_PER_GROWTH: usize = 2;
+
+/// Generates random slugs for routes created without one
+#[derive(Clone, Debug)]
+pub struct SlugGenerator {
+    alphabet: Vec<char>,
+    length: usize,
+}
+
+impl SlugGenerator {
+    pub fn new(alphabet: &str, length: usize) -> Result<Self, String> {
+        let alphabet: Vec<char> = alphabet.chars().collect();
+        if let Some(c) = alphabet.iter().find(|c| !is_url_safe(**c)) {
+            return Err(format!(
+                "slug alphabet may only contain letters, digits, '-', '.', '_' and '~', got {:?}",
+                c
+            ));
+        }
+        if let Some((index, c)) = alphabet
+            .iter()
+            .enumerate()
+            .find(|(index, c)| alphabet[..*index].contains(c))
+        {
+            return Err(format!(
+                "slug alphabet contains {:?} twice, at position {}",
+                c,
+                index + 1
+            ));
+        }
+        if alphabet.len() < 2 {
+            return Err("slug alphabet needs at least two characters".to_string());
+        }
+        if length == 0 {
+            return Err("slug length must be at least 1".to_string());
+        }
+        Ok(SlugGenerator { alphabet, length })
+    }
+
+    /// Configured starting length, slugs grow from here as the keyspace fills up
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Random slug of the given length
+    pub fn generate(&self, length: usize) -> String {
+        let mut rng = rand::thread_rng();
+        (0..length)
+            .map(|_| self.alphabet[rng.gen_range(0..self.alphabet.len())])
+            .collect()
+    }
+}
+
+/// Unreserved characters of RFC 3986, they never need percent-encoding in a path
+fn is_url_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~')
+}
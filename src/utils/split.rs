@@ -0,0 +1,149 @@
+use crate::models::route_targets::RouteTarget;
+use crate::utils::crypto::hash_ip;
+use actix_web::{http::header, HttpMessage, HttpRequest};
+use sodiumoxide::crypto::hash::sha256;
+use uuid::Uuid;
+
+/// Cookie keeping a visitor on the same variant of every split
+pub const VISITOR_COOKIE: &str = "elide_vid";
+/// Longest visitor id accepted from the cookie
+const MAX_VISITOR_LENGTH: usize = 128;
+
+/// Identifies the visitor for sticky assignment: the cookie when it is there,
+/// otherwise a salted hash of IP and User-Agent. The bool tells whether the
+/// cookie has to be set
+pub fn visitor_id(req: &HttpRequest, ip: Option<&str>, salt: &str) -> (String, bool) {
+    if let Some(cookie) = req.cookie(VISITOR_COOKIE) {
+        let value = cookie.value();
+        if !value.is_empty() && value.len() <= MAX_VISITOR_LENGTH {
+            return (value.to_string(), false);
+        }
+    }
+    let agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    // the first visit without cookie lands where the cookie will keep it
+    let visitor = hash_ip(&format!("{}|{}", ip.unwrap_or(""), agent), salt);
+    (visitor, true)
+}
+
+/// Picks a variant by weight, the same visitor always gets the same variant of a
+/// route as long as its variants and weights stay the same
+pub fn pick<'a>(
+    variants: &'a [RouteTarget],
+    visitor: &str,
+    route_id: Uuid,
+) -> Option<&'a RouteTarget> {
+    let total: u64 = variants
+        .iter()
+        .map(|variant| variant.weight.max(0) as u64)
+        .sum();
+    if total == 0 {
+        return None;
+    }
+
+    let digest = sha256::hash(format!("{}:{}", route_id, visitor).as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.0[..8]);
+    let mut point = u64::from_be_bytes(bytes) % total;
+
+    variants.iter().find(|variant| {
+        let weight = variant.weight.max(0) as u64;
+        if point < weight {
+            true
+        } else {
+            point -= weight;
+            false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+    use chrono::Utc;
+
+    fn variant(weight: i32) -> RouteTarget {
+        RouteTarget {
+            id: Uuid::new_v4(),
+            route_id: Uuid::nil(),
+            target: "https://example.com".to_string(),
+            weight,
+            label: None,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn same_visitor_same_variant() {
+        let variants = vec![variant(1), variant(1), variant(1)];
+        let route = Uuid::new_v4();
+        for visitor in &["a", "b", "c", "d"] {
+            let first = pick(&variants, visitor, route).unwrap().id;
+            for _ in 0..10 {
+                assert_eq!(pick(&variants, visitor, route).unwrap().id, first);
+            }
+        }
+    }
+
+    #[test]
+    fn weights_are_respected() {
+        let variants = vec![variant(1), variant(3)];
+        let route = Uuid::new_v4();
+        let heavy = (0..10_000)
+            .filter(|i| pick(&variants, &i.to_string(), route).unwrap().id == variants[1].id)
+            .count();
+        // 7500 expected, far enough off only when the hash is skewed
+        assert!((7_200..7_800).contains(&heavy), "{} of 10000", heavy);
+    }
+
+    #[test]
+    fn zero_weights_get_nothing() {
+        let variants = vec![variant(0), variant(2), variant(0)];
+        let route = Uuid::new_v4();
+        for i in 0..1_000 {
+            assert_eq!(
+                pick(&variants, &i.to_string(), route).unwrap().id,
+                variants[1].id
+            );
+        }
+        assert!(pick(&[variant(0), variant(0)], "a", route).is_none());
+        assert!(pick(&[], "a", route).is_none());
+    }
+
+    #[test]
+    fn cookie_visitor_is_kept() {
+        let req = TestRequest::default()
+            .cookie(Cookie::new(VISITOR_COOKIE, "abc"))
+            .to_http_request();
+        assert_eq!(
+            visitor_id(&req, Some("1.2.3.4"), "salt"),
+            ("abc".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn visitor_without_cookie_is_stable() {
+        let req = TestRequest::default()
+            .header(header::USER_AGENT, "curl/7.68.0")
+            .to_http_request();
+        let (first, set_cookie) = visitor_id(&req, Some("1.2.3.4"), "salt");
+        assert!(set_cookie);
+        assert_eq!(visitor_id(&req, Some("1.2.3.4"), "salt").0, first);
+        assert_ne!(visitor_id(&req, Some("1.2.3.5"), "salt").0, first);
+
+        // an oversized cookie is ignored like a missing one
+        let req = TestRequest::default()
+            .header(header::USER_AGENT, "curl/7.68.0")
+            .cookie(Cookie::new(
+                VISITOR_COOKIE,
+                "x".repeat(MAX_VISITOR_LENGTH + 1),
+            ))
+            .to_http_request();
+        assert_eq!(visitor_id(&req, Some("1.2.3.4"), "salt"), (first, true));
+    }
+}
@@ -0,0 +1,64 @@
+use std::borrow::Cow;
+use url::Url;
+use validator::ValidationError;
+
+pub const DEFAULT_SCHEMES: &[&str] = &["http", "https"];
+/// Longest target we are willing to put in a Location header
+pub const MAX_TARGET_LENGTH: usize = 2048;
+
+/// Decides which targets routes may point to and how they are stored
+#[derive(Clone, Debug)]
+pub struct TargetPolicy {
+    /// lowercase schemes a target may use
+    schemes: Vec<String>,
+}
+
+impl TargetPolicy {
+    pub fn new<S: AsRef<str>>(schemes: &[S]) -> Result<Self, String> {
+        let schemes: Vec<String> = schemes
+            .iter()
+            .map(|scheme| scheme.as_ref().trim().to_lowercase())
+            .filter(|scheme| !scheme.is_empty())
+            .collect();
+        if schemes.is_empty() {
+            return Err("at least one target scheme must be allowed".to_string());
+        }
+        Ok(TargetPolicy { schemes })
+    }
+
+    /// Parses and normalizes a target: absolute URL, allowed scheme, lowercase
+    /// IDNA encoded host, no default port and no credentials
+    pub fn normalize(&self, target: &str) -> Result<String, ValidationError> {
+        let target = target.trim();
+        if target.len() > MAX_TARGET_LENGTH {
+            let mut error = error("too_long", "target is too long");
+            error.add_param(Cow::from("max"), &MAX_TARGET_LENGTH);
+            return Err(error);
+        }
+
+        // the url crate lowercases and IDNA encodes hosts and drops default ports
+        let url = Url::parse(target)
+            .map_err(|_| error("invalid_url", "target must be an absolute URL"))?;
+
+        if !self.schemes.iter().any(|scheme| scheme == url.scheme()) {
+            let mut error = error("scheme_not_allowed", "target scheme is not allowed");
+            error.add_param(Cow::from("scheme"), &url.scheme());
+            error.add_param(Cow::from("allowed"), &self.schemes);
+            return Err(error);
+        }
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err(error(
+                "credentials_not_allowed",
+                "target must not contain a username or password",
+            ));
+        }
+
+        Ok(url.into())
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
@@ -0,0 +1,173 @@
+use hmac_sha1_compact::HMAC;
+use rand::Rng;
+use sodiumoxide::crypto::hash::sha256;
+use sodiumoxide::crypto::secretbox;
+
+/// Seconds each code is valid for, what authenticator apps assume
+pub const STEP_SECS: i64 = 30;
+/// Digits in a code
+pub const DIGITS: u32 = 6;
+/// Steps before and after the current one that are still accepted, for clock drift
+pub const SKEW_STEPS: i64 = 1;
+/// Bytes of a generated secret, 160 bits as RFC 4226 recommends
+const SECRET_LENGTH: usize = 20;
+
+/// Random secret for a new enrollment
+pub fn generate_secret() -> Vec<u8> {
+    sodiumoxide::init().unwrap();
+    sodiumoxide::randombytes::randombytes(SECRET_LENGTH)
+}
+
+/// Base32 form users type into authenticator apps
+pub fn encode_secret(secret: &[u8]) -> String {
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, secret)
+}
+
+/// `otpauth://` URI for QR codes
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    let label = format!("{}:{}", issuer, account);
+    let mut uri = url::Url::parse("otpauth://totp/").unwrap();
+    uri.path_segments_mut().unwrap().push(&label);
+    uri.query_pairs_mut()
+        .append_pair("secret", &encode_secret(secret))
+        .append_pair("issuer", issuer)
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECS.to_string());
+    uri.into()
+}
+
+/// RFC 6238 code for the time step `step`
+pub fn code_at(secret: &[u8], step: i64) -> String {
+    let mac = HMAC::mac(&step.to_be_bytes(), secret);
+    let offset = (mac[19] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        mac[offset] & 0x7f,
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// The time step `code` belongs to, None when it matches none close to `unix_time`
+pub fn matching_step(secret: &[u8], code: &str, unix_time: i64) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let current = unix_time / STEP_SECS;
+    (current - SKEW_STEPS..=current + SKEW_STEPS)
+        .find(|step| sodiumoxide::utils::memcmp(code_at(secret, *step).as_bytes(), code.as_bytes()))
+}
+
+/// Encrypts TOTP secrets at rest, so a database dump alone can't produce codes
+#[derive(Clone)]
+pub struct SecretCipher {
+    key: secretbox::Key,
+}
+
+impl SecretCipher {
+    /// Any string of at least 32 bytes, hashed down to a key
+    pub fn new(key: &str) -> Self {
+        sodiumoxide::init().unwrap();
+        SecretCipher {
+            key: secretbox::Key(sha256::hash(key.as_bytes()).0),
+        }
+    }
+
+    /// nonce followed by the ciphertext
+    pub fn seal(&self, secret: &[u8]) -> Vec<u8> {
+        let nonce = secretbox::gen_nonce();
+        let mut sealed = nonce.0.to_vec();
+        sealed.extend(secretbox::seal(secret, &nonce, &self.key));
+        sealed
+    }
+
+    /// None when `sealed` was not made with this key
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < secretbox::NONCEBYTES {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(secretbox::NONCEBYTES);
+        let nonce = secretbox::Nonce::from_slice(nonce)?;
+        secretbox::open(ciphertext, &nonce, &self.key).ok()
+    }
+}
+
+/// Recovery codes handed out when 2FA is enabled
+pub const RECOVERY_CODES: usize = 10;
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// One-time codes that stand in for a TOTP code when the device is lost
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| RECOVERY_ALPHABET[rng.gen_range(0..RECOVERY_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect()
+}
+
+/// What is hashed and compared, so case and dashes don't matter when typing
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret of the SHA1 vectors in RFC 6238 appendix B
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn rfc_6238_vectors() {
+        // the RFC lists 8 digits, these are their last 6
+        let vectors = [
+            (59, "287082"),
+            (1_111_111_109, "081804"),
+            (1_111_111_111, "050471"),
+            (1_234_567_890, "005924"),
+            (2_000_000_000, "279037"),
+            (20_000_000_000, "353130"),
+        ];
+        for (time, code) in vectors.iter() {
+            assert_eq!(code_at(SECRET, time / STEP_SECS), *code, "at {}", time);
+        }
+    }
+
+    #[test]
+    fn matching_step_allows_skew() {
+        let time = 1_111_111_109;
+        let step = time / STEP_SECS;
+        assert_eq!(matching_step(SECRET, "081804", time), Some(step));
+        assert_eq!(matching_step(SECRET, "081 804", time), Some(step));
+        assert_eq!(
+            matching_step(SECRET, "081804", time + STEP_SECS),
+            Some(step)
+        );
+        assert_eq!(
+            matching_step(SECRET, "081804", time - STEP_SECS),
+            Some(step)
+        );
+        assert_eq!(matching_step(SECRET, "081804", time + 2 * STEP_SECS), None);
+    }
+
+    #[test]
+    fn matching_step_rejects_wrong_codes() {
+        let time = 1_111_111_109;
+        assert_eq!(matching_step(SECRET, "081805", time), None);
+        assert_eq!(matching_step(SECRET, "81804", time), None);
+        assert_eq!(matching_step(SECRET, "", time), None);
+    }
+}
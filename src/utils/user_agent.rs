@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use woothee::parser::Parser;
+use woothee::woothee::VALUE_UNKNOWN;
+
+/// Operating systems route rules can target
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OsFamily {
+    Ios,
+    Android,
+    Windows,
+    Macos,
+    Linux,
+    Chromeos,
+}
+
+/// Kinds of device route rules can target
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceClass {
+    Desktop,
+    Mobile,
+    Tablet,
+}
+
+/// What we care about from a User-Agent header
+#[derive(Debug, Default)]
+pub struct UserAgentInfo {
+    /// browser family e.g. "Chrome", "Safari"
+    pub browser: Option<String>,
+    /// OS family e.g. "Windows 10", "iPhone", "Android"
+    pub os: Option<String>,
+    /// `os` as route rules know it
+    pub os_family: Option<OsFamily>,
+    pub device: Option<DeviceClass>,
+    /// known crawler
+    pub bot: bool,
+}
+
+pub fn parse(user_agent: &str) -> UserAgentInfo {
+    let known = |value: &str| {
+        if value.is_empty() || value == VALUE_UNKNOWN {
+            None
+        } else {
+            Some(value.to_string())
+        }
+    };
+
+    match Parser::new().parse(user_agent) {
+        Some(result) => UserAgentInfo {
+            browser: known(result.name),
+            os: known(result.os),
+            os_family: os_family(result.os),
+            device: device_class(result.category, result.os, user_agent),
+            bot: result.category == "crawler",
+        },
+        None => UserAgentInfo::default(),
+    }
+}
+
+fn os_family(os: &str) -> Option<OsFamily> {
+    match os {
+        "iPhone" | "iPad" | "iPod" | "iOS" => Some(OsFamily::Ios),
+        "Android" => Some(OsFamily::Android),
+        "Mac OSX" | "Mac OS Classic" => Some(OsFamily::Macos),
+        "Linux" => Some(OsFamily::Linux),
+        "ChromeOS" => Some(OsFamily::Chromeos),
+        os if os.starts_with("Windows") => Some(OsFamily::Windows),
+        _ => None,
+    }
+}
+
+fn device_class(category: &str, os: &str, user_agent: &str) -> Option<DeviceClass> {
+    match category {
+        "pc" => Some(DeviceClass::Desktop),
+        // woothee files tablets under smartphones, Android tablets leave out "Mobile"
+        "smartphone" | "mobilephone" => {
+            if os == "iPad" || (os == "Android" && !user_agent.contains("Mobile")) {
+                Some(DeviceClass::Tablet)
+            } else {
+                Some(DeviceClass::Mobile)
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 16_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.5 Mobile/15E148 Safari/604.1";
+    const IPAD: &str = "Mozilla/5.0 (iPad; CPU OS 12_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/12.1 Mobile/15E148 Safari/604.1";
+    const ANDROID_PHONE: &str = "Mozilla/5.0 (Linux; Android 13; Pixel 7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Mobile Safari/537.36";
+    const ANDROID_TABLET: &str = "Mozilla/5.0 (Linux; Android 12; SM-X700) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36";
+    const WINDOWS: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36";
+    const MACOS: &str =
+        "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:109.0) Gecko/20100101 Firefox/117.0";
+    const LINUX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/117.0";
+    const CHROMEOS: &str = "Mozilla/5.0 (X11; CrOS x86_64 14541.0.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36";
+    const GOOGLEBOT: &str =
+        "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+
+    #[test]
+    fn os_families() {
+        let cases = [
+            (IPHONE, Some(OsFamily::Ios)),
+            (IPAD, Some(OsFamily::Ios)),
+            (ANDROID_PHONE, Some(OsFamily::Android)),
+            (ANDROID_TABLET, Some(OsFamily::Android)),
+            (WINDOWS, Some(OsFamily::Windows)),
+            (MACOS, Some(OsFamily::Macos)),
+            (LINUX, Some(OsFamily::Linux)),
+            (CHROMEOS, Some(OsFamily::Chromeos)),
+            (GOOGLEBOT, None),
+        ];
+        for (agent, os) in cases.iter() {
+            assert_eq!(parse(agent).os_family, *os, "{}", agent);
+        }
+    }
+
+    #[test]
+    fn device_classes() {
+        let cases = [
+            (IPHONE, Some(DeviceClass::Mobile)),
+            (IPAD, Some(DeviceClass::Tablet)),
+            (ANDROID_PHONE, Some(DeviceClass::Mobile)),
+            (ANDROID_TABLET, Some(DeviceClass::Tablet)),
+            (WINDOWS, Some(DeviceClass::Desktop)),
+            (MACOS, Some(DeviceClass::Desktop)),
+            (GOOGLEBOT, None),
+        ];
+        for (agent, device) in cases.iter() {
+            assert_eq!(parse(agent).device, *device, "{}", agent);
+        }
+    }
+
+    #[test]
+    fn crawlers_and_garbage() {
+        let bot = parse(GOOGLEBOT);
+        assert!(bot.bot);
+        assert!(!parse(IPHONE).bot);
+
+        let unknown = parse("definitely not a browser");
+        assert!(unknown.browser.is_none() && unknown.os_family.is_none());
+        assert!(unknown.device.is_none() && !unknown.bot);
+    }
+}